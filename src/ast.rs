@@ -0,0 +1,109 @@
+//! The syntax tree produced by `parser`. It follows the shape of jsrs_common's AST, which it
+//! extends with the statements and operators that AST can't express.
+
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BinOp {
+    And,
+    Or,
+
+    Ge,
+    Gt,
+    Le,
+    Lt,
+    Neq,
+    Eql,
+    EqlStrict,
+    NeqStrict,
+
+    BitOr,
+    BitXor,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+    ShiftRightUnsigned,
+
+    Minus,
+    Plus,
+    Slash,
+    Star,
+    Mod,
+    Exponent,
+
+    In,
+    InstanceOf,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    BareExp(Exp),
+    /// `break;`, with an optional label.
+    Break(Option<String>),
+    /// `continue;`, with an optional label.
+    Continue(Option<String>),
+    /// `var name = exp;`
+    Decl(String, Exp),
+    /// `do { block } while (exp);`
+    DoWhile(Vec<Stmt>, Exp),
+    Empty,
+    /// `function name(params) { body }`, whose expression is the named `Defun`.
+    FnDecl(Exp),
+    /// `for (init; condition; update) { block }`. A missing condition is `true`, and missing
+    /// `init` or `update` statements are `Empty`.
+    For(Box<Stmt>, Exp, Box<Stmt>, Vec<Stmt>),
+    /// `for (target in exp) { block }`. The target is a `VarDecl` or a `BareExp`.
+    ForIn(Box<Stmt>, Exp, Vec<Stmt>),
+    /// `if (exp) { block } else { block }`. A missing `else` is an empty block.
+    If(Exp, Vec<Stmt>, Vec<Stmt>),
+    /// `label: stmt`
+    Labelled(String, Box<Stmt>),
+    Ret(Exp),
+    Seq(Box<Stmt>, Box<Stmt>),
+    /// `switch (exp) { ... }`, with one block for each `case exp:`, or `default:` if the
+    /// expression is missing.
+    Switch(Exp, Vec<(Option<Exp>, Vec<Stmt>)>),
+    Throw(Exp),
+    /// `try { block } catch (name) { block } finally { block }`. Without a `catch` clause, the
+    /// name is empty.
+    Try(Vec<Stmt>, String, Vec<Stmt>, Vec<Stmt>),
+    /// `var name;`
+    VarDecl(String),
+    While(Exp, Vec<Stmt>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Exp {
+    Array(Vec<Exp>),
+    Assign(Box<Exp>, Box<Exp>),
+    BinExp(Box<Exp>, BinOp, Box<Exp>),
+    BitNot(Box<Exp>),
+    Bool(bool),
+    Call(Box<Exp>, Vec<Exp>),
+    Comma(Box<Exp>, Box<Exp>),
+    CompoundAssign(Box<Exp>, BinOp, Box<Exp>),
+    Cond(Box<Exp>, Box<Exp>, Box<Exp>),
+    /// A function's name, parameters and body. The body is shared by every function value the
+    /// expression creates.
+    Defun(Option<String>, Vec<String>, Rc<Vec<Stmt>>),
+    Delete(Box<Exp>),
+    Float(f64),
+    InstanceVar(Box<Exp>, String),
+    KeyAccessor(Box<Exp>, Box<Exp>),
+    LogNot(Box<Exp>),
+    Neg(Box<Exp>),
+    NewObject(Box<Exp>, Vec<Exp>),
+    Null,
+    Object(Vec<(String, Box<Exp>)>),
+    Pos(Box<Exp>),
+    PostDec(Box<Exp>),
+    PostInc(Box<Exp>),
+    PreDec(Box<Exp>),
+    PreInc(Box<Exp>),
+    /// A string literal's contents, with escapes still in them.
+    Str(String),
+    TypeOf(Box<Exp>),
+    Undefined,
+    Var(String),
+    Void(Box<Exp>),
+}
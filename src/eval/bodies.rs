@@ -0,0 +1,72 @@
+//! Bodies of user-defined functions.
+//!
+//! `JsFnStruct` can only hold statements of jsrs_common's AST, which can't express most of what
+//! `parser` produces. So each scope manager has a table of the function bodies evaluated in it,
+//! and a `JsFnStruct` holds the index of its body in that table instead of the body itself. The
+//! table lives as long as the scope manager's `Engine`, which frees it with `drop_bodies`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ast::Stmt;
+
+use french_press::ScopeManager;
+use jsrs_common::ast as common;
+use jsrs_common::js_error::{self, JsError};
+use jsrs_common::types::js_fn::JsFnStruct;
+
+struct Bodies {
+    bodies: Vec<Rc<Vec<Stmt>>>,
+    /// Indices of the bodies in `bodies`, by address. The addresses can't be reused while the
+    /// table holds the bodies.
+    indices: HashMap<usize, usize>,
+}
+
+thread_local! {
+    /// The table of each scope manager, by the scope manager's address.
+    static BODIES: RefCell<HashMap<usize, Bodies>> = RefCell::new(HashMap::new());
+}
+
+fn state_address(state: &Rc<RefCell<ScopeManager>>) -> usize {
+    &**state as *const RefCell<ScopeManager> as usize
+}
+
+/// Create a function value whose body is kept in `state`'s table. Evaluating the same function
+/// expression again reuses its body's entry.
+pub fn new_fn(name: &Option<String>, params: &Vec<String>, body: &Rc<Vec<Stmt>>,
+              state: &Rc<RefCell<ScopeManager>>) -> JsFnStruct {
+    let address = &**body as *const Vec<Stmt> as usize;
+    let index = BODIES.with(|tables| {
+        let mut tables = tables.borrow_mut();
+        let table = tables.entry(state_address(state))
+                          .or_insert_with(|| Bodies { bodies: Vec::new(), indices: HashMap::new() });
+        if let Some(&index) = table.indices.get(&address) {
+            return index;
+        }
+        let index = table.bodies.len();
+        table.bodies.push(body.clone());
+        table.indices.insert(address, index);
+        index
+    });
+
+    // The index is stored as the statement `index;`, the only body `JsFnStruct` can hold.
+    let index_stmt = vec![common::Stmt::BareExp(common::Exp::Float(index as f64))];
+    JsFnStruct::new(name, params, &index_stmt)
+}
+
+/// Return the body of a function created by `new_fn` with the same `state`.
+pub fn fn_body(fun: &JsFnStruct, state: &Rc<RefCell<ScopeManager>>) -> js_error::Result<Rc<Vec<Stmt>>> {
+    let body = match fun.stmt.first() {
+        Some(&common::Stmt::BareExp(common::Exp::Float(index))) => BODIES.with(|tables| {
+            tables.borrow().get(&state_address(state)).and_then(|table| table.bodies.get(index as usize).cloned())
+        }),
+        _ => None,
+    };
+    body.ok_or_else(|| JsError::UnimplementedError(String::from("Function body not found, eval/bodies.rs")))
+}
+
+/// Free the table of `state`, whose functions can't be called afterwards.
+pub fn drop_bodies(state: &Rc<RefCell<ScopeManager>>) {
+    BODIES.with(|tables| tables.borrow_mut().remove(&state_address(state)));
+}
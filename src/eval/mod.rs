@@ -1,21 +1,26 @@
 #[macro_use]
 mod macros;
+mod bodies;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use native::get_array_proto;
-use number::eval_binop;
+use native::{call_native, from_js_error, get_array_proto, get_fn_prototype, get_global_object,
+             get_native_proto, get_native_proto_var, set_fn_prototype, to_object};
+pub use self::bodies::drop_bodies;
+use self::bodies::{fn_body, new_fn};
+use ast::*;
+use ast::Exp::*;
+use ast::Stmt::*;
+use number::{eval_binop, eval_binop_values, strict_equal, to_int32, to_number};
+use program::Program;
 use var::*;
 
 use french_press::ScopeManager;
-use jsrs_parser::lalr::parse_Stmt;
 use jsrs_common::gc_error::GcError;
 use jsrs_common::js_error::{self, JsError};
-use jsrs_common::ast::*;
-use jsrs_common::ast::Exp::*;
-use jsrs_common::ast::Stmt::*;
-use jsrs_common::types::coerce::{AsBool, AsNumber, AsString};
+use jsrs_common::ast as common;
+use jsrs_common::types::coerce::{AsBool, AsString};
 use jsrs_common::types::binding::Binding;
 use jsrs_common::types::js_fn::JsFnStruct;
 use jsrs_common::types::js_obj::JsObjStruct;
@@ -30,20 +35,27 @@ use unescape::unescape;
 /// Evaluate a string containing some JavaScript statements (or sequences of statements).
 /// Returns a JsVar which is the return value of those statements.
 pub fn eval_string(string: &str, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
-    match parse_Stmt(string) {
-        Ok(stmt) => {
-            Ok(try!(eval_stmt(&stmt, state)).0)
-        }
-        Err(e) => Err(JsError::ParseError(format!("{:?}", e))),
+    let program = try!(Program::parse(string));
+    eval_program(&program, state)
+}
+
+/// Evaluate a parsed script, returning the value of its last statement.
+pub fn eval_program(program: &Program, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    try!(hoist_global_declarations(&program.body, state.clone()));
+    match try!(eval_stmt_block(&program.body, state)) {
+        Completion::Break(_) => Err(JsError::SyntaxError(String::from("Illegal break statement"))),
+        Completion::Continue(_) => Err(JsError::SyntaxError(String::from("Illegal continue statement"))),
+        completion => Ok(completion.value()),
     }
 }
 
+/// Evaluate a block of statements, stopping at the first abrupt completion.
 pub fn eval_stmt_block(block: &Vec<Stmt>, state: Rc<RefCell<ScopeManager>>)
-        -> js_error::Result<(JsVarValue, JsReturnValue)> {
-    let mut ret = (scalar(JsUndef), None);
+        -> js_error::Result<Completion> {
+    let mut ret = Completion::Normal(scalar(JsUndef));
     for stmt in &*block {
         ret = try!(eval_stmt(stmt, state.clone()));
-        if let Some(..) = ret.1 {
+        if ret.is_abrupt() {
             return Ok(ret);
         }
     }
@@ -51,95 +63,25 @@ pub fn eval_stmt_block(block: &Vec<Stmt>, state: Rc<RefCell<ScopeManager>>)
 }
 
 /// Evaluate a single JS statement (which may be a block or sequence of statements).
-/// Returns tuple of (evaluated final value, return value), where return value requires that
-/// `return` be used to generate it.
+/// Returns the statement's completion; `throw` completions are returned as `Err`.
 pub fn eval_stmt(s: &Stmt, state: Rc<RefCell<ScopeManager>>)
-        -> js_error::Result<(JsVarValue, JsReturnValue)> {
+        -> js_error::Result<Completion> {
     match *s {
-        // var_string = exp;
-        Assign(ref lhs, ref exp) => {
-            let (rhs_var, rhs_ptr) = try!(eval_exp(exp, state.clone()));
-
-            let var = match lhs {
-                &Var(ref string) => {
-                    let result = state.borrow_mut().load(&Binding::new(string.to_owned()));
-                    let (mut var, ptr) = match result {
-                        Ok((v, p)) => (v, p),
-                        Err(GcError::Load(_)) => return eval_stmt(&Decl(string.to_owned(), exp.clone()), state.clone()),
-                        Err(e) => return Err(JsError::from(e)),
-                    };
-
-                    match ptr {
-                        Some(JsPtrEnum::NativeVar(mut native_var)) => {
-                            native_var.set(state.clone(), None, rhs_var, rhs_ptr);
-                            return Ok(((native_var.var, native_var.ptr.map(|x| *x)), None));
-                        }
-                        _ => ()
-                    };
-
-                    var.t = rhs_var.t.clone();
-                    let old_binding = var.unique.clone();
-                    var.mangle(string);
-                    let _ = state.borrow_mut().rename_closure(&old_binding, &var.unique);
-                    try!(state.borrow_mut().store(rhs_var.clone(), rhs_ptr.clone()));
-                    var
-                }
-                &InstanceVar(ref e, ref string) => {
-                    let (var, ptr) = try!(eval_exp(&e.clone(), state.clone()));
-
-                    let mut obj = match ptr.clone() {
-                        Some(JsPtrEnum::JsObj(obj)) => obj,
-                        _ => return Ok(((rhs_var, rhs_ptr), None))
-                    };
-
-
-                    let native_var = match obj.dict.get(&js_str_key(string)) {
-                        Some(ref var) => {
-                            let state_ref = state.borrow_mut();
-                            let alloc_box = state_ref.alloc_box.borrow_mut();
-                            alloc_box.find_id(&var.unique).map(|p| p.borrow().clone())
-                        }
-                        None => None
-                    };
-
-                    if let Some(JsPtrEnum::NativeVar(mut nv)) = native_var {
-                        nv.set(state.clone(), ptr.clone()
-                               .map(|x| (var.clone(), x)), rhs_var, rhs_ptr);
-                        return Ok(((nv.var.clone(), nv.clone().ptr.map(|x| *x)), None));
-                    }
-
-                    let mut state_ref = state.borrow_mut();
-                    obj.add_key(&var.unique,
-                                JsKey::JsStr(JsStrStruct::new(string)),
-                                rhs_var.clone(), rhs_ptr.clone(),
-                                &mut *(state_ref.alloc_box.borrow_mut()));
-                    rhs_var
-                }
-                _ => return Err(JsError::invalid_lhs())
-            };
-
-            Ok(((var, rhs_ptr), None))
-        },
+        // Function declarations are bound when their scope is entered; see `hoist_declarations`.
+        FnDecl(_) => Ok(Completion::Normal(scalar(JsUndef))),
 
         // exp;
-        BareExp(ref exp) => Ok((try!(eval_exp(exp, state.clone())), None)),
-        Break => Err(JsError::SyntaxError(String::from("Illegal break statement"))),
-        Continue => Err(JsError::SyntaxError(String::from("Illegal continue statement"))),
+        BareExp(ref exp) => Ok(Completion::Normal(try!(eval_exp(exp, state.clone())))),
+        Break(ref label) => Ok(Completion::Break(label.clone())),
+        Continue(ref label) => Ok(Completion::Continue(label.clone())),
 
         // var var_string = exp
+        // The variable was declared when its scope was entered, so this assigns to it, even from
+        // an inner scope like a `catch` block.
         Decl(ref var_string, ref exp) => {
-            let (mut js_var, js_ptr) = try!(eval_exp(exp, state.clone()));
-            let old_binding = js_var.unique.clone();
-            js_var.binding = Binding::new(var_string.clone());
-
-            let _ = state.borrow_mut().rename_closure(&old_binding, &js_var.unique);
-
-            match state.borrow_mut().alloc(js_var, js_ptr) {
-                Ok(_) => Ok((scalar(JsUndef), None)),
-                Err(e) => {
-                    Err(JsError::GcError(e))
-                }
-            }
+            let value = try!(eval_exp(exp, state.clone()));
+            try!(put_value(Reference::Var(var_string.clone()), value, state));
+            Ok(Completion::Normal(scalar(JsUndef)))
         },
 
         // if (condition) { if_block } else { else_block }
@@ -155,18 +97,41 @@ pub fn eval_stmt(s: &Stmt, state: Rc<RefCell<ScopeManager>>)
             }
         },
 
-        Empty => Ok((scalar(JsUndef), None)),
+        Empty => Ok(Completion::Normal(scalar(JsUndef))),
+
+        // label: stmt
+        Labelled(..) => {
+            let mut labels = Vec::new();
+            let mut stmt = s;
+            while let Labelled(ref label, ref inner) = *stmt {
+                labels.push(label.clone());
+                stmt = &**inner;
+            }
+
+            // Loops need their labels, since `continue` may name them.
+            let completion = match *stmt {
+                DoWhile(..) | For(..) | ForIn(..) | While(..) => try!(eval_loop(stmt, &labels, state)),
+                _ => try!(eval_stmt(stmt, state)),
+            };
+            match completion {
+                Completion::Break(Some(ref label)) if labels.contains(label) =>
+                    Ok(Completion::Normal(scalar(JsUndef))),
+                completion => Ok(completion),
+            }
+        }
 
         // return exp
         Ret(ref exp) => {
             let js_var = try!(eval_exp(&exp, state.clone()));
-            Ok((js_var.clone(), Some(js_var)))
+            Ok(Completion::Return(js_var))
         }
 
         // a sequence of any two expressions
         Seq(ref s1, ref s2) => {
-            try!(eval_stmt(&*s1, state.clone()));
-            eval_stmt(&*s2, state.clone())
+            match try!(eval_stmt(&*s1, state.clone())) {
+                Completion::Normal(_) => eval_stmt(&*s2, state.clone()),
+                abrupt => Ok(abrupt),
+            }
         },
 
         // throw <expression>;
@@ -177,61 +142,576 @@ pub fn eval_stmt(s: &Stmt, state: Rc<RefCell<ScopeManager>>)
 
         // try { block } [catch <expression> { block} &&/|| finally { block }]
         Try(ref try_block, ref catch_var, ref catch_block, ref finally_block) => {
-            match eval_stmt_block(try_block, state.clone()) {
-                Ok(_) => {
-                    try!(eval_stmt_block(finally_block, state.clone()));
-                    return Ok((scalar(JsUndef), None));
+            // A try statement with only a `finally` clause has no catch variable.
+            let result = match eval_stmt_block(try_block, state.clone()) {
+                Err(e) if !catch_var.is_empty() => eval_catch(e, catch_var, catch_block, state.clone()),
+                result => result,
+            };
+
+            // Meta errors (e.g. a failed `$ERROR`) abort the script without running `finally`.
+            if let Err(ref e) = result {
+                if e.is_meta_error() {
+                    return result;
                 }
-                Err(_) => {
-                    // TODO: figure out how to bind error to variable in scope.
-                    let mut var = JsVar::new(JsType::JsPtr(JsPtrTag::JsObj));
-                    var.binding = Binding::new(catch_var.clone());
-                    {
-                        let mut state_ref = state.borrow_mut();
-                        let obj = JsObjStruct::new(None, "", Vec::new(),
-                            &mut *(state_ref.alloc_box.borrow_mut()));
-                        // Add error to scope.
-                        state_ref.push_scope(&Exp::Null);
-                        try!(state_ref.alloc(var, Some(JsPtrEnum::JsObj(obj))));
+            }
+
+            // The finally block always runs, and if it completes abruptly, that completion
+            // replaces the one from the try or catch block.
+            match try!(eval_stmt_block(finally_block, state.clone())) {
+                Completion::Normal(_) => result,
+                abrupt => Ok(abrupt),
+            }
+        }
+
+        // var var_string; the variable itself was declared by `hoist_declarations`.
+        VarDecl(_) => Ok(Completion::Normal(scalar(JsUndef))),
+
+        // switch (exp) { case exp: block ... default: block ... }
+        Switch(ref exp, ref cases) => {
+            let discriminant = try!(eval_exp(exp, state.clone()));
+
+            // Cases are tested in order, with `default` chosen only if none match wherever it
+            // appears. Evaluation then falls through every clause after the chosen one.
+            let mut start = None;
+            for (i, &(ref test, _)) in cases.iter().enumerate() {
+                if let Some(ref test) = *test {
+                    if strict_equal(&discriminant, &try!(eval_exp(test, state.clone()))) {
+                        start = Some(i);
+                        break;
                     }
+                }
+            }
+            let start = match start.or(cases.iter().position(|&(ref test, _)| test.is_none())) {
+                Some(i) => i,
+                None => return Ok(Completion::Normal(scalar(JsUndef))),
+            };
 
-                    try!(eval_stmt_block(catch_block, state.clone()));
+            let mut value = scalar(JsUndef);
+            for &(_, ref block) in &cases[start..] {
+                match try!(eval_stmt_block(block, state.clone())) {
+                    Completion::Normal(v) => value = v,
+                    Completion::Break(None) => break,
+                    abrupt => return Ok(abrupt),
+                }
+            }
+            Ok(Completion::Normal(value))
+        }
 
-                    try!(state.borrow_mut().pop_scope(None, false));
+        // Loops
+        DoWhile(..) | For(..) | ForIn(..) | While(..) => eval_loop(s, &[], state),
+    }
+}
 
-                    try!(eval_stmt_block(finally_block, state.clone()));
-                    return Ok((scalar(JsUndef), None));
+/// Evaluate an iteration statement, which is labelled with `labels`.
+fn eval_loop(s: &Stmt, labels: &[String], state: Rc<RefCell<ScopeManager>>) -> js_error::Result<Completion> {
+    match *s {
+        // do { block } while (condition)
+        DoWhile(ref block, ref condition) => {
+            let mut value = scalar(JsUndef);
+            loop {
+                if let Some(completion) = try!(eval_loop_body(block, labels, &mut value, state.clone())) {
+                    return Ok(completion);
+                }
+                if !try!(eval_exp(&condition, state.clone())).0.as_bool() {
+                    return Ok(Completion::Normal(value));
                 }
             }
         }
 
-        // while (condition) { block }
-        VarDecl(ref s) => {
-            let (mut var, ptr) = scalar(JsUndef);
-            var.binding = Binding::new(s.clone());
-            match state.borrow_mut().alloc(var, ptr) {
-                Ok(_) => Ok((scalar(JsUndef), None)),
-                Err(e) => {
-                    Err(JsError::GcError(e))
+        // for (init; condition; update) { block }
+        For(ref init, ref condition, ref update, ref block) => {
+            try!(eval_stmt(init, state.clone()));
+            let mut value = scalar(JsUndef);
+            while try!(eval_exp(&condition, state.clone())).0.as_bool() {
+                if let Some(completion) = try!(eval_loop_body(block, labels, &mut value, state.clone())) {
+                    return Ok(completion);
+                }
+                try!(eval_stmt(update, state.clone()));
+            }
+            Ok(Completion::Normal(value))
+        }
+
+        // for (var_string in exp) { block }
+        // for (lhs in exp) { block }
+        ForIn(ref target, ref exp, ref block) => {
+            let lhs = match **target {
+                VarDecl(ref name) => Var(name.clone()),
+                BareExp(ref lhs) => lhs.clone(),
+                _ => return Err(JsError::invalid_lhs()),
+            };
+
+            // Enumerating `null` or `undefined` doesn't run the body at all (ES5 §12.6.4).
+            let (var, ptr) = try!(eval_exp(exp, state.clone()));
+            let keys = match var.t {
+                JsNull | JsUndef => Vec::new(),
+                _ => match try!(to_object(state.clone(), var, ptr)) {
+                    (_, JsPtrEnum::JsObj(ref obj)) => enumerable_keys(obj, state.clone()),
+                    _ => Vec::new(),
+                },
+            };
+
+            let mut value = scalar(JsUndef);
+            for key in keys {
+                let key_var = JsVar::new(JsPtr(JsPtrTag::JsStr));
+                let key_ptr = Some(JsPtrEnum::JsStr(JsStrStruct::new(&key)));
+                try!(state.borrow_mut().alloc(key_var.clone(), key_ptr.clone()));
+                try!(assign(&lhs, (key_var, key_ptr), state.clone()));
+
+                if let Some(completion) = try!(eval_loop_body(block, labels, &mut value, state.clone())) {
+                    return Ok(completion);
                 }
             }
+            Ok(Completion::Normal(value))
         }
 
         // while (condition) { block }
         While(ref condition, ref block) => {
+            let mut value = scalar(JsUndef);
             loop {
                 if try!(eval_exp(&condition, state.clone())).0.as_bool() {
-                    let (js_var, js_ret_var) = try!(eval_stmt_block(block, state.clone()));
-                    if let Some(..) = js_ret_var {
-                        return Ok((js_var, js_ret_var));
+                    if let Some(completion) = try!(eval_loop_body(block, labels, &mut value, state.clone())) {
+                        return Ok(completion);
                     }
                 } else {
-                    // condition is no longer true, return a return value
-                    return Ok((scalar(JsUndef), None));
+                    // condition is no longer true, return the value of the last iteration
+                    return Ok(Completion::Normal(value));
                 }
             }
         }
+        _ => eval_stmt(s, state),
+    }
+}
+
+/// Evaluate one iteration of a loop's body, recording its value in `value`. Returns the completion
+/// the loop finishes with, or `None` if it should go on to its next iteration. `continue` may name
+/// any of the loop's `labels`; a `break` naming one is handled by the `Labelled` statement.
+fn eval_loop_body(block: &Vec<Stmt>, labels: &[String], value: &mut JsVarValue, state: Rc<RefCell<ScopeManager>>)
+        -> js_error::Result<Option<Completion>> {
+    match try!(eval_stmt_block(block, state)) {
+        Completion::Normal(v) => {
+            *value = v;
+            Ok(None)
+        }
+        Completion::Continue(None) => Ok(None),
+        Completion::Continue(Some(ref label)) if labels.contains(label) => Ok(None),
+        Completion::Break(None) => Ok(Some(Completion::Normal(value.clone()))),
+        abrupt => Ok(Some(abrupt)),
+    }
+}
+
+/// A resolved assignment target (ES5 §8.7). Properties hold their already-evaluated object, so
+/// that compound assignment evaluates the target only once.
+enum Reference {
+    Var(String),
+    Property(JsVarValue, String),
+}
+
+/// Evaluate the parts of an assignment target, without reading its value.
+fn eval_reference(lhs: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<Reference> {
+    match *lhs {
+        Var(ref string) => Ok(Reference::Var(string.clone())),
+        InstanceVar(ref e, ref string) => Ok(Reference::Property(try!(eval_exp(e, state)), string.clone())),
+        KeyAccessor(ref e, ref key) => {
+            let obj = try!(eval_exp(e, state.clone()));
+            let (var, ptr) = try!(eval_exp(key, state));
+            let string = ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&var.t));
+            Ok(Reference::Property(obj, string))
+        }
+        _ => Err(JsError::invalid_lhs()),
+    }
+}
+
+/// Read the current value of a reference.
+fn get_value(reference: &Reference, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    match *reference {
+        Reference::Var(ref string) => eval_exp(&Var(string.clone()), state),
+        Reference::Property((ref var, ref ptr), ref string) =>
+            instance_var_eval!(var.clone(), ptr.clone(), string, state),
+    }
+}
+
+/// Assign `rhs` to a reference, returning the value of the assignment.
+fn put_value(reference: Reference, rhs: JsVarValue, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let (rhs_var, rhs_ptr) = rhs;
+    let var = match reference {
+        Reference::Var(ref string) => {
+            let result = state.borrow_mut().load(&Binding::new(string.to_owned()));
+            let (mut var, ptr) = match result {
+                Ok((v, p)) => (v, p),
+                // Assigning to an undeclared variable declares it.
+                Err(GcError::Load(_)) => {
+                    let mut var = rhs_var.clone();
+                    let old_binding = var.unique.clone();
+                    var.binding = Binding::new(string.to_owned());
+                    let _ = state.borrow_mut().rename_closure(&old_binding, &var.unique);
+                    try!(state.borrow_mut().alloc(var, rhs_ptr.clone()));
+                    return Ok((rhs_var, rhs_ptr));
+                }
+                Err(e) => return Err(JsError::from(e)),
+            };
+
+            match ptr {
+                Some(JsPtrEnum::NativeVar(mut native_var)) => {
+                    native_var.set(state.clone(), None, rhs_var, rhs_ptr);
+                    return Ok((native_var.var, native_var.ptr.map(|x| *x)));
+                }
+                _ => ()
+            };
+
+            var.t = rhs_var.t.clone();
+            let old_binding = var.unique.clone();
+            var.mangle(string);
+            let _ = state.borrow_mut().rename_closure(&old_binding, &var.unique);
+            try!(state.borrow_mut().store(rhs_var.clone(), rhs_ptr.clone()));
+            var
+        }
+        Reference::Property((var, ptr), string) => {
+            let mut obj = match ptr.clone() {
+                Some(JsPtrEnum::JsObj(obj)) => obj,
+                Some(JsPtrEnum::JsFn(_)) if string == "prototype" => {
+                    try!(set_fn_prototype(&var, rhs_ptr.clone(), state.clone()));
+                    return Ok((rhs_var, rhs_ptr));
+                }
+                _ => return Ok((rhs_var, rhs_ptr))
+            };
+
+
+            // Native accessors (e.g. an array's `length`) may live further up the
+            // prototype chain; any other write shadows on the receiver itself.
+            let native_var = match find_property(&obj, &js_str_key(&string), state.clone()) {
+                Some(var) => {
+                    let state_ref = state.borrow_mut();
+                    let alloc_box = state_ref.alloc_box.borrow_mut();
+                    alloc_box.find_id(&var.unique).map(|p| p.borrow().clone())
+                }
+                None => None
+            };
+
+            if let Some(JsPtrEnum::NativeVar(mut nv)) = native_var {
+                nv.set(state.clone(), ptr.clone()
+                       .map(|x| (var.clone(), x)), rhs_var, rhs_ptr);
+                return Ok((nv.var.clone(), nv.clone().ptr.map(|x| *x)));
+            }
+
+            let mut state_ref = state.borrow_mut();
+            obj.add_key(&var.unique,
+                        JsKey::JsStr(JsStrStruct::new(&string)),
+                        rhs_var.clone(), rhs_ptr.clone(),
+                        &mut *(state_ref.alloc_box.borrow_mut()));
+            rhs_var
+        }
+    };
+
+    Ok((var, rhs_ptr))
+}
+
+/// Evaluate `lhs = exp`, or `lhs op= exp` if `op` is given, returning the assigned value.
+fn eval_assign(lhs: &Exp, op: Option<&BinOp>, exp: &Exp, state: Rc<RefCell<ScopeManager>>)
+        -> js_error::Result<JsVarValue> {
+    let reference = try!(eval_reference(lhs, state.clone()));
+    let rhs = match op {
+        Some(op) => {
+            let old = try!(get_value(&reference, state.clone()));
+            let rhs = try!(eval_exp(exp, state.clone()));
+            try!(eval_binop_values(op, old, rhs, state.clone()))
+        }
+        None => try!(eval_exp(exp, state.clone())),
+    };
+    put_value(reference, rhs, state)
+}
+
+/// Evaluate `++` or `--` (ES5 §11.3, §11.4.4, §11.4.5), adding `delta` to the target. Prefix
+/// operators return the new value, postfix ones the old value converted to a number.
+fn eval_update(exp: &Exp, delta: f64, prefix: bool, state: Rc<RefCell<ScopeManager>>)
+        -> js_error::Result<JsVarValue> {
+    let reference = try!(eval_reference(exp, state.clone()));
+    let old = try!(to_number(try!(get_value(&reference, state.clone())), state.clone()));
+
+    try!(put_value(reference, scalar(JsNum(old + delta)), state));
+    Ok(scalar(JsNum(if prefix { old + delta } else { old })))
+}
+
+/// Assign `rhs` to the target `lhs` (a variable or an object property), returning the value of
+/// the assignment.
+fn assign(lhs: &Exp, rhs: JsVarValue, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let reference = try!(eval_reference(lhs, state.clone()));
+    put_value(reference, rhs, state)
+}
+
+/// Evaluate a catch block, with the caught error bound to `catch_var` in a new scope.
+fn eval_catch(e: JsError, catch_var: &str, catch_block: &Vec<Stmt>, state: Rc<RefCell<ScopeManager>>)
+        -> js_error::Result<Completion> {
+    // Meta errors (e.g. a failed `$ERROR`) are not catchable, so they keep propagating.
+    let (mut var, ptr) = try!(from_js_error(state.clone(), e));
+    var.binding = Binding::new(catch_var.to_owned());
+    {
+        let mut state_ref = state.borrow_mut();
+        // Add error to scope.
+        state_ref.push_scope(&common::Exp::Null);
+        try!(state_ref.alloc(var, ptr));
+    }
+
+    let catch_result = eval_stmt_block(catch_block, state.clone());
+
+    try!(state.borrow_mut().pop_scope(None, false));
+    catch_result
+}
+
+/// Collect the declarations in `stmt` which are bound on entry to its function or program
+/// (ES5 §10.5): the names of its `var`s, and its function declarations. The bodies of nested
+/// functions have their own declarations, so they aren't searched.
+fn collect_declarations<'a>(stmt: &'a Stmt, vars: &mut Vec<String>, fns: &mut Vec<&'a Exp>) {
+    match *stmt {
+        Decl(ref name, _) | VarDecl(ref name) => {
+            if !vars.contains(name) {
+                vars.push(name.clone());
+            }
+        }
+        FnDecl(ref exp) => fns.push(exp),
+        If(_, ref if_block, ref else_block) => {
+            for stmt in if_block.iter().chain(else_block.iter()) {
+                collect_declarations(stmt, vars, fns);
+            }
+        }
+        Seq(ref s1, ref s2) => {
+            collect_declarations(s1, vars, fns);
+            collect_declarations(s2, vars, fns);
+        }
+        Try(ref try_block, _, ref catch_block, ref finally_block) => {
+            for stmt in try_block.iter().chain(catch_block.iter()).chain(finally_block.iter()) {
+                collect_declarations(stmt, vars, fns);
+            }
+        }
+        While(_, ref block) | DoWhile(ref block, _) => {
+            for stmt in block {
+                collect_declarations(stmt, vars, fns);
+            }
+        }
+        Labelled(_, ref inner) => collect_declarations(inner, vars, fns),
+        For(ref init, _, _, ref block) | ForIn(ref init, _, ref block) => {
+            collect_declarations(init, vars, fns);
+            for stmt in block {
+                collect_declarations(stmt, vars, fns);
+            }
+        }
+        Switch(_, ref cases) => {
+            for &(_, ref block) in cases {
+                for stmt in block {
+                    collect_declarations(stmt, vars, fns);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Bind the function declarations in `body` and declare its `var`s as `undefined` in the current
+/// scope, before any of `body` runs. Variables for which `is_bound` holds keep their values.
+fn hoist_declarations(body: &[Stmt], is_bound: &Fn(&str) -> bool, state: Rc<RefCell<ScopeManager>>)
+        -> js_error::Result<()> {
+    let mut vars = Vec::new();
+    let mut fns = Vec::new();
+    for stmt in body {
+        collect_declarations(stmt, &mut vars, &mut fns);
+    }
+
+    for name in vars {
+        if is_bound(&name) {
+            continue;
+        }
+        let (mut var, ptr) = scalar(JsUndef);
+        var.binding = Binding::new(name);
+        try!(state.borrow_mut().alloc(var, ptr));
+    }
+
+    // Function declarations replace variables of the same name.
+    for exp in fns {
+        try!(eval_exp(exp, state.clone()));
+    }
+    Ok(())
+}
+
+/// Hoist the declarations of a script into the global scope. Redeclaring a global doesn't
+/// reset it.
+fn hoist_global_declarations(body: &[Stmt], state: Rc<RefCell<ScopeManager>>) -> js_error::Result<()> {
+    let global_state = state.clone();
+    let is_bound = move |name: &str| global_state.borrow_mut().load(&Binding::new(name.to_owned())).is_ok();
+    hoist_declarations(body, &is_bound, state)
+}
+
+/// The type `to_primitive` should prefer when converting an object (ES5 §9.1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hint {
+    Number,
+    String,
+}
+
+/// Convert a value to a primitive (ES5 §9.1, §8.12.8). Objects are converted by calling their
+/// `valueOf` and `toString` methods, in the order `hint` prefers, until one returns a primitive.
+pub fn to_primitive(value: JsVarValue, hint: Hint, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let (var, ptr) = value;
+    let obj = match ptr {
+        Some(JsPtrEnum::JsObj(obj)) => obj,
+        // Functions have no properties to look methods up in, so they convert to their source.
+        Some(fun @ JsPtrEnum::JsFn(_)) | Some(fun @ JsPtrEnum::NativeFn(_)) => {
+            let s = JsPtrEnum::JsStr(JsStrStruct::new(&fun.as_string()));
+            return Ok((JsVar::new(JsPtr(JsPtrTag::JsStr)), Some(s)));
+        }
+        ptr => return Ok((var, ptr)),
+    };
+
+    let methods = match hint {
+        Hint::Number => ["valueOf", "toString"],
+        Hint::String => ["toString", "valueOf"],
+    };
+
+    for name in methods.iter() {
+        let (method_var, method_ptr) = match find_property(&obj, &js_str_key(name), state.clone()) {
+            Some(method_var) => {
+                let state_ref = state.borrow_mut();
+                let ptr = state_ref.alloc_box.borrow_mut().find_id(&method_var.unique).map(|p| p.borrow().clone());
+                (method_var, ptr)
+            }
+            None => continue,
+        };
+
+        let this = Some((var.clone(), JsPtrEnum::JsObj(obj.clone())));
+        let result = match method_ptr {
+            Some(JsPtrEnum::JsFn(fun)) => try!(call_js_fn(&method_var, fun, this, Vec::new(), state.clone())),
+            Some(JsPtrEnum::NativeFn(fun)) => try!(call_native(&fun, state.clone(), this, Vec::new())),
+            _ => continue,
+        };
+
+        match result.1 {
+            Some(JsPtrEnum::JsObj(_)) | Some(JsPtrEnum::JsFn(_)) | Some(JsPtrEnum::NativeFn(_)) => (),
+            _ => return Ok(result),
+        }
+    }
+
+    Err(JsError::TypeError(String::from("Cannot convert object to primitive value")))
+}
+
+/// Convert a value to a string (ES5 §9.8). Objects are converted by `to_primitive` first, which
+/// can call their `toString` methods.
+pub fn to_string(value: JsVarValue, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<String> {
+    let (var, ptr) = try!(to_primitive(value, Hint::String, state));
+    Ok(ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&var.t)))
+}
+
+/// Call a user-defined function with the given `this` and arguments.
+fn call_js_fn(fun_var: &JsVar, js_fn_struct: JsFnStruct, this: Option<(JsVar, JsPtrEnum)>,
+              args: Vec<JsVarValue>, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let body = try!(fn_body(&js_fn_struct, &state));
+
+    match js_fn_struct.name {
+        // Named functions tag their scope with a call to themselves.
+        Some(ref name) => {
+            let call = common::Exp::Call(Box::new(common::Exp::Var(name.clone())), Vec::new());
+            state.borrow_mut().push_scope(&call)
+        }
+        None => try!(state.borrow_mut().push_closure_scope(&fun_var.unique))
+    };
+
+    // The scope is popped whether or not the body completes normally.
+    let result = eval_fn_body(&js_fn_struct, &body, this, args, state.clone());
+    let v = match result {
+        Ok(Completion::Return(ref v)) => Some(v.clone()),
+        _ => None,
+    };
+
+    // If the return value of a function is `None` (void),
+    // or is not a pointer to a function, a closure is not being
+    // returned from the function. If the function is returning a
+    // function, and the function being returned has no name, a closure
+    // is being returned.
+    let returning_closure = v.as_ref().map_or(None, |ref var| {
+        match var.0.t {
+            JsType::JsPtr(ref tag) => match tag {
+                &JsPtrTag::JsFn {..} => Some(var.0.unique.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    });
+
+    // Should we yield here? Not sure, so for now it doesn't
+    state.borrow_mut().pop_scope(returning_closure, false)
+        .expect("Unable to clear scope for function");
+
+    match try!(result) {
+        Completion::Break(_) => Err(JsError::SyntaxError(String::from("Illegal break statement"))),
+        Completion::Continue(_) => Err(JsError::SyntaxError(String::from("Illegal continue statement"))),
+        _ => Ok(v.unwrap_or(scalar(JsUndef))),
+    }
+}
+
+/// Bind a function's arguments and `this` in its freshly pushed scope, then evaluate its body.
+fn eval_fn_body(js_fn_struct: &JsFnStruct, body: &Vec<Stmt>, this: Option<(JsVar, JsPtrEnum)>,
+                mut args: Vec<JsVarValue>, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<Completion> {
+    for param in js_fn_struct.params.iter() {
+        let mut arg = if args.is_empty() {
+            scalar(JsUndef)
+        } else {
+            args.remove(0)
+        };
+
+        arg.0.binding = Binding::new(param.to_owned());
+        state.borrow_mut().alloc(arg.0, arg.1)
+        .expect("Unable to store function argument in scope");
+    }
+
+    // Method calls see their receiver as `this`; plain calls see the global object.
+    let (mut this_var, this_ptr) = match this {
+        Some((var, ptr)) => (var, Some(ptr)),
+        None => get_global_object(state.clone()),
+    };
+    this_var.binding = Binding::new(String::from("this"));
+    try!(state.borrow_mut().alloc(this_var, this_ptr));
+
+    // Declaring a `var` with the same name as a parameter doesn't reset the parameter.
+    let is_param = |name: &str| js_fn_struct.params.iter().any(|p| p == name);
+    try!(hoist_declarations(body, &is_param, state.clone()));
+
+    eval_stmt_block(body, state)
+}
+
+/// Evaluate `delete exp` (ES5 §11.4.1), returning whether the property is gone. Declared
+/// variables and natively implemented properties (built-in methods, an array's `length`) can't
+/// be deleted.
+fn eval_delete(exp: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<bool> {
+    let ((obj_var, obj_ptr), key) = match *exp {
+        InstanceVar(ref obj_exp, ref key) => (try!(eval_exp(obj_exp, state.clone())), key.clone()),
+        KeyAccessor(ref obj_exp, ref key_exp) => {
+            let obj = try!(eval_exp(obj_exp, state.clone()));
+            let (var, ptr) = try!(eval_exp(key_exp, state.clone()));
+            (obj, ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&var.t)))
+        }
+        // Deleting an undeclared variable has nothing to do, and succeeds.
+        Var(ref name) => return Ok(state.borrow_mut().load(&Binding::new(name.clone())).is_err()),
+        _ => {
+            try!(eval_exp(exp, state));
+            return Ok(true);
+        }
+    };
+
+    let mut obj = match obj_ptr {
+        Some(JsPtrEnum::JsObj(obj)) => obj,
+        _ => return Ok(true),
+    };
+
+    let key = js_str_key(&key);
+    let deletable = match obj.dict.get(&key) {
+        Some(&JsVar { t: JsPtr(JsPtrTag::NativeFn { .. }), .. }) |
+        Some(&JsVar { t: JsPtr(JsPtrTag::NativeVar { .. }), .. }) => false,
+        Some(_) => true,
+        None => return Ok(true),
+    };
+
+    if deletable {
+        let state_ref = state.borrow_mut();
+        obj.remove_key(&obj_var.unique, &key, &mut *(state_ref.alloc_box.borrow_mut()));
     }
+    Ok(deletable)
 }
 
 /// Evaluate an expression into a JsVar.
@@ -257,23 +737,30 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
             Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsObj)), Some(JsPtrEnum::JsObj(obj))))
         }
         &BitNot(ref exp) => {
-            let i = try!(eval_exp(exp, state.clone())).0.as_number() as i32;
-            Ok(scalar(JsNum((!i) as f64)))
+            let n = try!(to_number(try!(eval_exp(exp, state.clone())), state.clone()));
+            Ok(scalar(JsNum((!to_int32(n)) as f64)))
         }
+        // lhs = e
+        &Exp::Assign(ref lhs, ref e) => eval_assign(lhs, None, e, state),
+
         // e1 [op] e2
         &BinExp(ref e1, ref op, ref e2) => {
-            let result = try!(eval_binop(op, e1, e2, state.clone()));
-            Ok(scalar(result))
+            eval_binop(op, e1, e2, state.clone())
         }
         &Bool(b) => Ok(scalar(JsBool(b))),
 
         // fun_name([arg_exp1, arg_exps])
         &Call(ref fun_name, ref arg_exps) => {
+            // Calling a property passes its object as `this`.
             let ((fun_binding, fun_ptr), this) = match **fun_name {
-                InstanceVar(ref lhs, ref name) => {
-                    let (obj_var, obj_ptr) = try!(eval_exp(lhs, state.clone()));
-                    let state_clone = state.clone();
-                    (try!(instance_var_eval!(obj_var.clone(), obj_ptr.clone(), name, state_clone)), obj_ptr.map(|x| (obj_var, x)))
+                InstanceVar(..) | KeyAccessor(..) => {
+                    let reference = try!(eval_reference(fun_name, state.clone()));
+                    let fun = try!(get_value(&reference, state.clone()));
+                    match reference {
+                        Reference::Property((obj_var, obj_ptr), _) =>
+                            (fun, Some(try!(to_object(state.clone(), obj_var, obj_ptr)))),
+                        Reference::Var(_) => (fun, None),
+                    }
                 }
                 _ => (try!(eval_exp(fun_name, state.clone())), None)
             };
@@ -286,7 +773,7 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
 
             let js_fn_struct = match fun_ptr {
                 Some(JsPtrEnum::JsFn(fun)) => fun,
-                Some(JsPtrEnum::NativeFn(func)) => return func.call(state.clone(), this, args),
+                Some(JsPtrEnum::NativeFn(func)) => return call_native(&func, state.clone(), this, args),
                 Some(_) =>
                     return Err(JsError::TypeError(format!("{:?} is not a function", fun_name))),
                 None => match state.borrow_mut().load(&fun_binding.binding) {
@@ -298,51 +785,31 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
                 }
             };
 
-            match js_fn_struct.name {
-                Some(_) => state.borrow_mut().push_scope(e),
-                None => try!(state.borrow_mut().push_closure_scope(&fun_binding.unique))
-            };
-
-            for param in js_fn_struct.params {
-                let mut arg = if args.is_empty() {
-                    scalar(JsUndef)
-                } else {
-                    args.remove(0)
-                };
-
-                arg.0.binding = Binding::new(param.to_owned());
-                state.borrow_mut().alloc(arg.0, arg.1)
-                .expect("Unable to store function argument in scope");
-            }
+            call_js_fn(&fun_binding, js_fn_struct, this, args, state)
+        }
 
-            let (_, v) = try!(eval_stmt_block(&js_fn_struct.stmt, state.clone()));
-
-            // If the return value of a function is `None` (void),
-            // or is not a pointer to a function, a closure is not being
-            // returned from the function. If the function is returning a
-            // function, and the function being returned has no name, a closure
-            // is being returned.
-            let returning_closure = v.as_ref().map_or(None, |ref var| {
-                match var.0.t {
-                    JsType::JsPtr(ref tag) => match tag {
-                        &JsPtrTag::JsFn {..} => Some(var.0.unique.clone()),
-                        _ => None,
-                    },
-                    _ => None,
-                }
-            });
+        // lhs op= e
+        &Exp::CompoundAssign(ref lhs, ref op, ref e) => eval_assign(lhs, Some(op), e, state),
 
-            // Should we yield here? Not sure, so for now it doesn't
-            state.borrow_mut().pop_scope(returning_closure, false)
-                .expect("Unable to clear scope for function");
+        // e1, e2
+        &Comma(ref e1, ref e2) => {
+            try!(eval_exp(e1, state.clone()));
+            eval_exp(e2, state)
+        }
 
-            Ok(v.unwrap_or(scalar(JsUndef)))
+        // condition ? e1 : e2
+        &Cond(ref condition, ref e1, ref e2) => {
+            if try!(eval_exp(condition, state.clone())).0.as_bool() {
+                eval_exp(e1, state)
+            } else {
+                eval_exp(e2, state)
+            }
         }
 
         // function([param1, params]) { body }
         // function opt_binding([param1, params]) { body }
         &Defun(ref opt_binding, ref params, ref body) => {
-            let js_fun = JsFnStruct::new(opt_binding, params, body);
+            let js_fun = new_fn(opt_binding, params, body, &state);
 
             let var = if let &Some(ref s) = opt_binding {
                 JsVar::bind(s, JsPtr(JsPtrTag::JsFn { name: opt_binding.clone() }))
@@ -353,10 +820,14 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
             if let Err(e) = state.borrow_mut().alloc(var.clone(), Some(JsPtrEnum::JsFn(js_fun.clone()))) {
                 return Err(JsError::GcError(e));
             }
+            try!(get_fn_prototype(&var, state.clone()));
 
             Ok((var, Some(JsPtrEnum::JsFn(js_fun))))
         },
 
+        // delete exp
+        &Delete(ref exp) => Ok(scalar(JsBool(try!(eval_delete(exp, state))))),
+
         // var.binding
         &InstanceVar(ref instance_exp, ref var) => {
             // TODO: this needs better type-reasoning and errors
@@ -368,21 +839,71 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
         &Float(f) => Ok(scalar(JsType::JsNum(f))),
         &KeyAccessor(ref obj, ref key) => {
             let (var, ptr) = try!(eval_exp(key, state.clone()));
-            let string = ptr.map(|p| p.as_string()).unwrap_or(var.t.as_string());
+            let string = ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&var.t));
             eval_exp(&InstanceVar(obj.clone(), string), state)
         }
         &LogNot(ref exp) => Ok(scalar(JsBool(!try!(eval_exp(exp, state.clone())).0.as_bool()))),
-        &Neg(ref exp) => Ok(scalar(JsNum(-try!(eval_exp(exp, state.clone())).0.as_number()))),
+        &Neg(ref exp) => Ok(scalar(JsNum(-try!(to_number(try!(eval_exp(exp, state.clone())), state))))),
         &Null => Ok(scalar(JsNull)),
-        &Pos(ref exp) => Ok(scalar(JsNum(try!(eval_exp(exp, state.clone())).0.as_number()))),
+        &Pos(ref exp) => Ok(scalar(JsNum(try!(to_number(try!(eval_exp(exp, state.clone())), state))))),
+
+        &PostDec(ref exp) => eval_update(exp, -1.0, false, state),
+        &PostInc(ref exp) => eval_update(exp, 1.0, false, state),
+        &PreDec(ref exp)  => eval_update(exp, -1.0, true, state),
+        &PreInc(ref exp)  => eval_update(exp, 1.0, true, state),
+
+        // new fun_name([arg_exp1, arg_exps])
+        &NewObject(ref fun_name, ref arg_exps) => {
+            let (fun_var, fun_ptr) = try!(eval_exp(fun_name, state.clone()));
+
+            let mut args = Vec::new();
+            for exp in arg_exps {
+                args.push(try!(eval_exp(exp, state.clone())));
+            }
+
+            let js_fn_struct = match fun_ptr {
+                Some(JsPtrEnum::JsFn(fun)) => fun,
+                // Native constructors (`Number`, `Object`, ...) build their own return values;
+                // `new` only wraps the primitives returned by `String`, `Number` and `Boolean`.
+                Some(JsPtrEnum::NativeFn(func)) => {
+                    let (var, ptr) = try!(call_native(&func, state.clone(), None, args));
+                    return match fun_var.t {
+                        JsPtr(JsPtrTag::NativeFn { ref name })
+                            if name == "String" || name == "Number" || name == "Boolean" => {
+                            let (var, ptr) = try!(to_object(state.clone(), var, ptr));
+                            Ok((var, Some(ptr)))
+                        }
+                        _ => Ok((var, ptr)),
+                    };
+                }
+                _ => return Err(JsError::TypeError(format!("{:?} is not a constructor", fun_name))),
+            };
 
-        &PostDec(ref exp) => eval_float_post_op!(exp, f, f - 1.0, state),
-        &PostInc(ref exp) => eval_float_post_op!(exp, f, f + 1.0, state),
-        &PreDec(ref exp)  => eval_float_pre_op!(exp, f, f - 1.0, state),
-        &PreInc(ref exp)  => eval_float_pre_op!(exp, f, f + 1.0, state),
+            let proto = match try!(get_fn_prototype(&fun_var, state.clone())) {
+                (_, Some(JsPtrEnum::JsObj(proto))) => Some(Box::new(proto)),
+                _ => get_native_proto("Object", state.clone()).map(Box::new),
+            };
 
-        &NewObject(ref proto, ref args) => {
-            eval_exp(&Call(proto.clone(), args.clone()), state.clone())
+            let this_var = JsVar::new(JsPtr(JsPtrTag::JsObj));
+            let this_obj = {
+                let state_ref = state.borrow_mut();
+                let obj = JsObjStruct::new(proto, "Object", Vec::new(), &mut *(state_ref.alloc_box.borrow_mut()));
+                obj
+            };
+            try!(state.borrow_mut().alloc(this_var.clone(), Some(JsPtrEnum::JsObj(this_obj.clone()))));
+
+            let this = Some((this_var.clone(), JsPtrEnum::JsObj(this_obj)));
+            let (ret_var, ret_ptr) = try!(call_js_fn(&fun_var, js_fn_struct, this, args, state.clone()));
+
+            // An explicitly returned object replaces the newly constructed one.
+            match ret_ptr {
+                Some(JsPtrEnum::JsObj(_)) | Some(JsPtrEnum::JsFn(_)) => Ok((ret_var, ret_ptr)),
+                _ => {
+                    let state_ref = state.borrow_mut();
+                    let this_ptr = state_ref.alloc_box.borrow_mut().find_id(&this_var.unique).map(|p| p.borrow().clone());
+                    Ok((this_var, this_ptr))
+                }
+            }
         }
         &Object(ref fields) => {
             let mut kv_tuples = Vec::new();
@@ -399,8 +920,9 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
                 kv_tuples.push((f_key, f_var, f_ptr));
             }
 
+            let proto = get_native_proto("Object", state.clone()).map(Box::new);
             let mut state_ref = state.borrow_mut();
-            let obj = JsObjStruct::new(None, "Object", kv_tuples, &mut *(state_ref.alloc_box.borrow_mut()));
+            let obj = JsObjStruct::new(proto, "Object", kv_tuples, &mut *(state_ref.alloc_box.borrow_mut()));
 
             Ok((JsVar::new(JsPtr(JsPtrTag::JsObj)), Some(JsPtrEnum::JsObj(obj))))
         }
@@ -424,6 +946,10 @@ pub fn eval_exp(e: &Exp, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<J
                     Some(JsPtrEnum::JsStr(JsStrStruct::new(&try!(eval_exp(e, state.clone())).0.type_of())))
             )),
         &Undefined => Ok(scalar(JsUndef)),
+        &Void(ref e) => {
+            try!(eval_exp(e, state));
+            Ok(scalar(JsUndef))
+        }
         &Var(ref var_binding) => {
             match state.borrow_mut().load(&Binding::new(var_binding.clone())) {
                 Ok((var, ptr)) => Ok((var, ptr)),
@@ -439,7 +965,9 @@ mod test {
     use std::cell::RefCell;
     use std::rc::Rc;
     use french_press::init_gc;
+    use jsrs_common::types::coerce::AsString;
     use jsrs_common::types::js_var::JsType;
+    use native::{add_pervasives, caller_to_string, register_native_fn};
 
     #[test]
     fn test_eval_literals() {
@@ -478,6 +1006,426 @@ mod test {
         //assert_eq!(&JsNumber(1.0f64), state.get("a").unwrap());
     }
 
+    #[test]
+    fn test_this_binding() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("var o = { x: 4.0, f: function() { return this.x; } };\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("o.f();\n", state.clone()).unwrap().0.t);
+        eval_string("function g() { return this; }\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsBool(true), eval_string("g() === this;\n", state.clone()).unwrap().0.t);
+
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("o[\"f\"]();\n", state.clone()).unwrap().0.t);
+        eval_string("var arr = [];\narr[\"push\"](1.0);\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("arr.length;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_new_object() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("function Point(x) { this.x = x; return 1.0; }\n", state.clone()).unwrap();
+        eval_string("var p = new Point(3.0);\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("p.x;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("p instanceof Point;\n", state.clone()).unwrap().0.t);
+
+        eval_string("function Other() { return { y: 2.0 }; }\n", state.clone()).unwrap();
+        eval_string("var o = new Other();\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("o.y;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(false), eval_string("o instanceof Other;\n", state.clone()).unwrap().0.t);
+
+        // A native constructor and its arguments are evaluated once.
+        eval_string("var n = 0.0;\nfunction getNumber() { n++; return Number; }\n", state.clone()).unwrap();
+        let (_, ptr) = eval_string("var w = new (getNumber())(n++);\ntypeof w;\n", state.clone()).unwrap();
+        assert_eq!("object", ptr.unwrap().as_string());
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("n;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_proto_chain() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("function Animal() {}\n", state.clone()).unwrap();
+        eval_string("Animal.prototype.legs = 4.0;\n", state.clone()).unwrap();
+        eval_string("var a = new Animal();\n", state.clone()).unwrap();
+        eval_string("var b = new Animal();\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("a.legs;\n", state.clone()).unwrap().0.t);
+
+        eval_string("a.legs = 3.0;\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("a.legs;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("b.legs;\n", state.clone()).unwrap().0.t);
+
+        assert_eq!(JsType::JsBool(true), eval_string("a.hasOwnProperty(\"legs\");\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(false), eval_string("b.hasOwnProperty(\"legs\");\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("a instanceof Object;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_shared_prototypes() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("function Animal() {}\n", state.clone()).unwrap();
+        eval_string("var a = new Animal();\n", state.clone()).unwrap();
+        eval_string("var o = {};\n", state.clone()).unwrap();
+
+        // Prototypes extended after an object was created are seen through it.
+        eval_string("Animal.prototype.legs = 4.0;\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("a.legs;\n", state.clone()).unwrap().0.t);
+        eval_string("Object.prototype.x = 1.0;\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("o.x;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("a.x;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("\"x\" in a;\n", state.clone()).unwrap().0.t);
+
+        eval_string("Animal.prototype.legs = 2.0;\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("a.legs;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_primitive_properties() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("\"abc\".length;\n", state.clone()).unwrap().0.t);
+
+        let (_, ptr) = eval_string("(5).toFixed(2);\n", state.clone()).unwrap();
+        assert_eq!("5.00", ptr.unwrap().as_string());
+        let (_, ptr) = eval_string("(0.5).toFixed(0);\n", state.clone()).unwrap();
+        assert_eq!("1", ptr.unwrap().as_string());
+        let (_, ptr) = eval_string("(2.5).toFixed(0);\n", state.clone()).unwrap();
+        assert_eq!("3", ptr.unwrap().as_string());
+        let (_, ptr) = eval_string("(1.005).toFixed(2);\n", state.clone()).unwrap();
+        assert_eq!("1.00", ptr.unwrap().as_string());
+
+        let (_, ptr) = eval_string("true.toString();\n", state.clone()).unwrap();
+        assert_eq!("true", ptr.unwrap().as_string());
+
+        assert!(eval_string("undefined.x;\n", state.clone()).is_err());
+    }
+
+    fn native_str(_state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>, args: Vec<JsVarValue>)
+                  -> js_error::Result<JsVarValue> {
+        let string = try!(caller_to_string(args[0].clone()));
+        Ok((JsVar::new(JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&string)))))
+    }
+
+    #[test]
+    fn test_to_string() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        let value = eval_string("[1e21, 0.5];\n", state.clone()).unwrap();
+        assert_eq!("1e+21,0.5", to_string(value, state.clone()).unwrap());
+        let value = eval_string("({ toString: function() { return 1e-7; } });\n", state.clone()).unwrap();
+        assert_eq!("1e-7", to_string(value, state.clone()).unwrap());
+        let value = eval_string("-0;\n", state.clone()).unwrap();
+        assert_eq!("0", to_string(value, state.clone()).unwrap());
+
+        // Natives convert their arguments through the calling script.
+        register_native_fn(state.clone(), "str", native_str).unwrap();
+        let (_, ptr) = eval_string("str([1e21]);\n", state.clone()).unwrap();
+        assert_eq!("1e+21", ptr.unwrap().as_string());
+    }
+
+    #[test]
+    fn test_error_classes() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("var e = new TypeError(\"bad\");\n", state.clone()).unwrap();
+        assert_eq!("bad", eval_string("e.message;\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!("TypeError", eval_string("e.name;\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!("TypeError: bad", eval_string("e.toString();\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!(JsType::JsBool(true), eval_string("e instanceof TypeError;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("e instanceof Error;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(false), eval_string("e instanceof RangeError;\n", state.clone()).unwrap().0.t);
+        assert_eq!("Error", eval_string("Error().toString();\n", state.clone()).unwrap().1.unwrap().as_string());
+    }
+
+    #[test]
+    fn test_catch_binding() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("var caught = 0.0;\n", state.clone()).unwrap();
+        eval_string("try { throw 5.0; } catch (e) { caught = e; }\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(5.0f64), eval_string("caught;\n", state.clone()).unwrap().0.t);
+
+        eval_string("try { notDefined; } catch (e) { caught = e instanceof ReferenceError; }\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsBool(true), eval_string("caught;\n", state.clone()).unwrap().0.t);
+
+        eval_string("try { throw new TypeError(\"bad\"); } catch (e) { caught = e.message; }\n", state.clone()).unwrap();
+        assert_eq!("bad", eval_string("caught;\n", state.clone()).unwrap().1.unwrap().as_string());
+
+        assert!(eval_string("try { $ERROR(\"fail\"); } catch (e) {}\n", state.clone()).is_err());
+
+        // A function which throws leaves no bindings behind.
+        eval_string("function f(x) { throw 1.0; }\ntry { f(5.0); } catch (e) {}\n", state.clone()).unwrap();
+        assert_eq!("undefined", eval_string("typeof x;\n", state.clone()).unwrap().1.unwrap().as_string());
+    }
+
+    #[test]
+    fn test_finally() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string("function f() { try { return 1.0; } finally { } }\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("f();\n", state.clone()).unwrap().0.t);
+
+        eval_string("function g() { try { return 1.0; } finally { return 2.0; } }\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("g();\n", state.clone()).unwrap().0.t);
+
+        eval_string("var ran = false;\n", state.clone()).unwrap();
+        eval_string("function h() { try { throw 1.0; } catch (e) { throw 2.0; } finally { ran = true; } }\n",
+                    state.clone()).unwrap();
+        assert!(eval_string("h();\n", state.clone()).is_err());
+        assert_eq!(JsType::JsBool(true), eval_string("ran;\n", state.clone()).unwrap().0.t);
+
+        assert!(eval_string("try { throw 3.0; } finally { }\n", state.clone()).is_err());
+
+        eval_string("function k() { try { throw 1.0; } finally { return 4.0; } }\n", state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("k();\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_break_continue() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        eval_string("var i = 0.0;\n", state.clone()).unwrap();
+        eval_string("var n = 0.0;\n", state.clone()).unwrap();
+        eval_string("while (true) { i++; if (i === 5.0) { break; } if (i === 2.0) { continue; } n++; }\n",
+                    state.clone()).unwrap();
+        assert_eq!(JsType::JsNum(5.0f64), eval_string("i;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("n;\n", state.clone()).unwrap().0.t);
+
+        assert!(eval_string("break;\n", state.clone()).is_err());
+    }
+
+    #[test]
+    fn test_loops() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(10.0f64),
+                   eval_string("var s = 0; for (var i = 0; i < 5; i++) { s = s + i; } s;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64),
+                   eval_string("for (i = 0; ; i++) { if (i == 3) { break; } } i;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64),
+                   eval_string("var n = 0; do { n++; } while (false); n;\n", state.clone()).unwrap().0.t);
+
+        // Own and inherited keys are visited once each; built-in methods aren't enumerable.
+        assert_eq!(JsType::JsNum(2.0f64),
+                   eval_string("function P() {} P.prototype.a = 1; var o = new P(); o.a = 2; o.b = 3; \
+                                var c = 0; for (var k in o) { c++; } c;\n", state.clone()).unwrap().0.t);
+        let (_, ptr) = eval_string("var last; for (last in [7, 8, 9]) {} last;\n", state.clone()).unwrap();
+        assert_eq!("2", ptr.unwrap().as_string());
+        // A string's characters are enumerable, its length isn't.
+        let (_, ptr) = eval_string("var ks = \"\"; for (var k in \"ab\") { ks = ks + k; } ks;\n", state.clone()).unwrap();
+        assert_eq!("01", ptr.unwrap().as_string());
+        assert_eq!(JsType::JsUndef,
+                   eval_string("for (var u in null) { u = 1; } u;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_switch() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        assert_eq!(JsType::JsNum(3.0f64),
+                   eval_string("var r = 0; switch (2) { case 1: r = 1; case 2: r = r + 1; case 3: r = r + 2; } r;\n",
+                               state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(4.0f64),
+                   eval_string("switch (5) { case 1: r = 1; break; default: r = 4; break; case 2: r = 2; } r;\n",
+                               state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(2.0f64),
+                   eval_string("switch (2) { default: r = 4; case 2: r = 2; } r;\n", state.clone()).unwrap().0.t);
+
+        // Matching is strict, so "1" doesn't match 1.
+        assert_eq!(JsType::JsNum(0.0f64),
+                   eval_string("r = 0; switch (\"1\") { case 1: r = 1; } r;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_labels() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(3.0f64),
+                   eval_string("var n = 0; outer: for (var i = 0; i < 3; i++) { \
+                                for (var j = 0; j < 3; j++) { if (j == 1) { continue outer; } n++; } } n;\n",
+                               state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64),
+                   eval_string("n = 0; a: b: while (true) { while (true) { n++; break a; } } n;\n",
+                               state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64),
+                   eval_string("n = 0; block: { n++; if (true) { break block; } n++; } n;\n", state.clone()).unwrap().0.t);
+
+        // Bad jumps are rejected before anything runs.
+        match eval_string("n = 5; while (true) { break nowhere; }\n", state.clone()) {
+            Err(JsError::SyntaxError(_)) => (),
+            r => panic!("expected SyntaxError, got {:?}", r),
+        }
+        assert!(eval_string("block: { continue block; }\n", state.clone()).is_err());
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("n;\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_operators() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("true ? 1 : 2;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("0 ? 1 : 2;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("var c = 0; c++, c++, c + 1;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsUndef, eval_string("void c++;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("c;\n", state.clone()).unwrap().0.t);
+
+        assert_eq!(JsType::JsBool(true), eval_string("var o = { a: 1 }; delete o.a;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(false), eval_string("\"a\" in o;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("delete o.missing;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(false), eval_string("delete c;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(false), eval_string("delete [1].length;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("delete undeclared;\n", state.clone()).unwrap().0.t);
+
+        // The object is evaluated before the key.
+        let (_, ptr) = eval_string("var order = \"\";\ndelete (order += \"o\", o)[(order += \"k\", \"a\")];\norder;\n",
+                                   state.clone()).unwrap();
+        assert_eq!("ok", ptr.unwrap().as_string());
+
+        // `in` walks the prototype chain, and needs an object on its right.
+        assert_eq!(JsType::JsBool(true), eval_string("\"toString\" in o;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsBool(true), eval_string("0 in [5];\n", state.clone()).unwrap().0.t);
+        assert!(eval_string("\"a\" in 1;\n", state.clone()).is_err());
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(7.0f64), eval_string("var a = 5; a += 2; a;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(12.0f64), eval_string("a -= 1; a *= 2; a;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("a /= 3; a %= 3; a;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(6.0f64), eval_string("a |= 6; a &= 7; a ^= 1; a;\n", state.clone()).unwrap().0.t);
+
+        assert_eq!(JsType::JsNum(3.0f64),
+                   eval_string("var o = { n: 1 }; o.n += 2; o.n;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(10.0f64),
+                   eval_string("var arr = [1, 2]; arr[1] *= 5; arr[1];\n", state.clone()).unwrap().0.t);
+
+        // The target is evaluated once.
+        assert_eq!(JsType::JsNum(1.0f64),
+                   eval_string("var i = 0; var xs = [0, 0]; xs[i++] += 4; i;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(4.0f64), eval_string("xs[0];\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_assignment_expression() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("var a; var b; a = b = 3; a;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("b;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(2.0f64),
+                   eval_string("var c; if ((c = 2) > 1) { c; } else { 0; }\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(5.0f64),
+                   eval_string("var n = 0; while ((n += 1) < 5) {} n;\n", state.clone()).unwrap().0.t);
+
+        // Setting an array's length through an assignment expression still truncates it.
+        assert_eq!(JsType::JsUndef,
+                   eval_string("var arr = [1, 2, 3]; var z = (arr.length = 0); arr[0];\n", state.clone()).unwrap().0.t);
+    }
+
+    #[test]
+    fn test_update_operators() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("var a = 1; a++;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64), eval_string("++a;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("--a;\n", state.clone()).unwrap().0.t);
+
+        assert_eq!(JsType::JsNum(6.0f64),
+                   eval_string("var o = { count: 5 }; o.count++; o.count;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64),
+                   eval_string("var arr = [3, 2]; var i = 1; arr[i]--; arr[1];\n", state.clone()).unwrap().0.t);
+
+        // Operands are converted to numbers.
+        assert_eq!(JsType::JsNum(5.0f64), eval_string("var s = \"5\"; s++;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(6.0f64), eval_string("s;\n", state.clone()).unwrap().0.t);
+
+        match eval_string("missing++;\n", state.clone()) {
+            Err(JsError::ReferenceError(_)) => (),
+            r => panic!("expected ReferenceError, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_addition() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        assert_eq!("ab", eval_string("\"a\" + \"b\";\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!("atrue", eval_string("\"a\" + true;\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!("nullundefined",
+                   eval_string("\"null\" + undefined;\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!("abc",
+                   eval_string("var s = \"a\"; s += \"b\"; s + \"c\";\n", state.clone()).unwrap().1.unwrap().as_string());
+
+        // Objects are converted with valueOf, then toString.
+        assert_eq!("[object Object]!",
+                   eval_string("var obj = {}; obj + \"!\";\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!("x,y", eval_string("[\"x\"] + [\"y\"];\n", state.clone()).unwrap().1.unwrap().as_string());
+        assert_eq!(JsType::JsNum(5.0f64),
+                   eval_string("var o = { valueOf: function() { return 4; } }; o + 1;\n", state.clone()).unwrap().0.t);
+
+        // Without a string operand, + adds numbers.
+        assert_eq!(JsType::JsNum(2.0f64), eval_string("true + 1;\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("null + 1;\n", state.clone()).unwrap().0.t);
+        match eval_string("undefined + 1;\n", state.clone()).unwrap().0.t {
+            JsType::JsNum(n) => assert!(n.is_nan()),
+            t => panic!("expected NaN, got {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_function_bodies() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        let other = Rc::new(RefCell::new(init_gc()));
+        let fun = match eval_string("(function () { return 1; });\n", state.clone()).unwrap().1 {
+            Some(JsPtrEnum::JsFn(fun)) => fun,
+            ptr => panic!("expected a function, got {:?}", ptr),
+        };
+        assert_eq!(1, fn_body(&fun, &state).unwrap().len());
+        assert!(fn_body(&fun, &other).is_err());
+
+        drop_bodies(&state);
+        assert!(fn_body(&fun, &state).is_err());
+    }
+
+    #[test]
+    fn test_program() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        let program = Program::parse("var a = 1\nvar b = a + 1\nb").unwrap();
+        assert_eq!(3, program.body.len());
+        assert_eq!(JsType::JsNum(2.0f64), eval_program(&program, state.clone()).unwrap().0.t);
+
+        match Program::parse("var c = 1;\nvar d = ;\n") {
+            Err(JsError::ParseError(ref message)) => assert!(message.contains("line 2, column 9")),
+            _ => panic!("expected a parse error"),
+        }
+        assert!(eval_string("c;\n", state.clone()).is_err());
+    }
+
+    #[test]
+    fn test_hoisting() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        let program = Program::parse("var x = f();\nfunction f() { return 1; }\nx;").unwrap();
+        assert_eq!(JsType::JsNum(1.0f64), eval_program(&program, state.clone()).unwrap().0.t);
+
+        let (_, ptr) = eval_string("var b = typeof z; var z = 1; b;\n", state.clone()).unwrap();
+        assert_eq!("undefined", ptr.unwrap().as_string());
+
+        assert_eq!(JsType::JsUndef,
+                   eval_string("var g = 1; function h() { var r = g; var g = 2; return r; } h();\n",
+                               state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(3.0f64),
+                   eval_string("function k(a) { var a; return a; } k(3);\n", state.clone()).unwrap().0.t);
+        assert_eq!(JsType::JsNum(1.0f64), eval_string("var x; x;\n", state.clone()).unwrap().0.t);
+
+        // A `var` in a `catch` block belongs to the enclosing function or script.
+        assert_eq!(JsType::JsNum(2.0f64),
+                   eval_string("try { throw 1.0; } catch (e) { var y = 2.0; }\ny;\n", state.clone()).unwrap().0.t);
+
+        // A function expression isn't a declaration.
+        let (_, ptr) = eval_string("var early = typeof q;\n(function q() {});\nearly;\n", state.clone()).unwrap();
+        assert_eq!("undefined", ptr.unwrap().as_string());
+    }
+
     #[test]
     fn test_binexp() {
         let state = Rc::new(RefCell::new(init_gc()));
@@ -0,0 +1,552 @@
+//! A tokenizer for ECMAScript 5 source text (ES5 §7).
+//!
+//! Besides splitting source into tokens, this handles the parts of the lexical grammar which need
+//! context: comments (including `//` inside string and regex literals), and telling a regular
+//! expression literal from a division. Tokens record the line terminators before them, which the
+//! parser needs for automatic semicolon insertion (ES5 §7.9).
+
+use jsrs_common::js_error::{self, JsError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind {
+    Ident,
+    Keyword,
+    Punct,
+    Num,
+    Str,
+    Regex,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// The token's source text. String and regex literals keep their quotes and escapes.
+    pub text: String,
+    /// 1-based line of the token's first character.
+    pub line: usize,
+    /// 1-based column of the token's first character.
+    pub column: usize,
+    /// Whether a line terminator (possibly inside a comment) separates this token from the
+    /// previous one.
+    pub newline_before: bool,
+}
+
+impl Token {
+    /// Whether this is the keyword or punctuator `text`.
+    pub fn is(&self, text: &str) -> bool {
+        (self.kind == TokenKind::Punct || self.kind == TokenKind::Keyword) && self.text == text
+    }
+
+    fn is_keyword_in(&self, keywords: &[&str]) -> bool {
+        self.kind == TokenKind::Keyword && keywords.contains(&&*self.text)
+    }
+}
+
+const KEYWORDS: &'static [&'static str] = &[
+    "break", "case", "catch", "continue", "debugger", "default", "delete", "do", "else", "false",
+    "finally", "for", "function", "if", "in", "instanceof", "new", "null", "return", "switch",
+    "this", "throw", "true", "try", "typeof", "var", "void", "while", "with",
+];
+
+/// Punctuators, longest first so that the first match is the longest one.
+const PUNCTUATORS: &'static [&'static str] = &[
+    ">>>=", "===", "!==", ">>>", "<<=", ">>=", "**=",
+    "<=", ">=", "==", "!=", "&&", "||", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+    "<<", ">>", "**",
+    "{", "}", "(", ")", "[", "]", ";", ",", "<", ">", "+", "-", "*", "/", "%", "&", "|", "^", "!",
+    "~", "?", ":", "=", ".",
+];
+
+/// Keywords after which an expression, and not an operator, is expected.
+const KEYWORDS_BEFORE_EXPRESSION: &'static [&'static str] = &[
+    "case", "delete", "do", "else", "in", "instanceof", "new", "return", "throw", "typeof", "void",
+];
+
+/// Keywords which may end an expression or statement.
+const KEYWORDS_ENDING_STATEMENT: &'static [&'static str] = &[
+    "break", "continue", "debugger", "false", "null", "return", "this", "true",
+];
+
+/// Keywords whose parenthesized header is followed by a statement rather than ending one.
+const CONTROL_KEYWORDS: &'static [&'static str] = &["catch", "for", "if", "switch", "while", "with"];
+
+fn is_line_terminator(c: char) -> bool {
+    c == '\n' || c == '\r' || c == '\u{2028}' || c == '\u{2029}'
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '$' || c == '_' || c.is_alphabetic()
+}
+
+fn is_ident_part(c: char) -> bool {
+    is_ident_start(c) || c.is_numeric()
+}
+
+fn lex_error(message: &str, line: usize, column: usize) -> JsError {
+    JsError::ParseError(format!("{} (line {}, column {})", message, line, column))
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.pos += 1;
+            // Treat "\r\n" as a single line terminator.
+            if is_line_terminator(c) && !(c == '\r' && self.peek() == Some('\n')) {
+                self.line += 1;
+                self.column = 1;
+            } else if !is_line_terminator(c) {
+                self.column += 1;
+            }
+        }
+        c
+    }
+
+    /// Skips whitespace and comments, returning whether a line terminator was skipped.
+    fn skip_trivia(&mut self) -> js_error::Result<bool> {
+        let mut newline = false;
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (Some(c), _) if is_line_terminator(c) => {
+                    newline = true;
+                    self.bump();
+                }
+                (Some(c), _) if c.is_whitespace() || c == '\u{feff}' => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    while let Some(c) = self.peek() {
+                        if is_line_terminator(c) {
+                            break;
+                        }
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let (line, column) = (self.line, self.column);
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match (self.peek(), self.peek_at(1)) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                self.bump();
+                                break;
+                            }
+                            (Some(c), _) => {
+                                // A multi-line comment containing a line terminator counts as one.
+                                newline = newline || is_line_terminator(c);
+                                self.bump();
+                            }
+                            (None, _) => return Err(lex_error("Unterminated comment", line, column)),
+                        }
+                    }
+                }
+                _ => return Ok(newline),
+            }
+        }
+    }
+
+    fn read_while<F: Fn(char) -> bool>(&mut self, text: &mut String, pred: F) {
+        while let Some(c) = self.peek() {
+            if !pred(c) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+    }
+
+    /// Reads an identifier name, decoding any `\uXXXX` escapes in it (ES5 §7.6).
+    fn read_ident(&mut self, line: usize, column: usize) -> js_error::Result<String> {
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.bump();
+                    let digits: String = (0..5).filter_map(|i| self.peek_at(i)).collect();
+                    let code = if digits.starts_with('u') && digits[1..].chars().all(|c| c.is_digit(16)) {
+                        u32::from_str_radix(&digits[1..], 16).ok()
+                    } else {
+                        None
+                    };
+                    let decoded = code.and_then(::std::char::from_u32);
+                    match decoded {
+                        Some(c) if digits.len() == 5 && (is_ident_part(c) && !text.is_empty() || is_ident_start(c)) => {
+                            for _ in 0..5 {
+                                self.bump();
+                            }
+                            text.push(c);
+                        }
+                        _ => return Err(lex_error("Invalid Unicode escape in identifier", line, column)),
+                    }
+                }
+                Some(c) if is_ident_part(c) => {
+                    text.push(c);
+                    self.bump();
+                }
+                _ => return Ok(text),
+            }
+        }
+    }
+
+    fn read_number(&mut self, line: usize, column: usize) -> js_error::Result<String> {
+        let mut text = String::new();
+        let is_hex = self.peek() == Some('0') &&
+            (self.peek_at(1) == Some('x') || self.peek_at(1) == Some('X'));
+
+        if is_hex {
+            text.push(self.bump().unwrap());
+            text.push(self.bump().unwrap());
+            self.read_while(&mut text, |c| c.is_digit(16));
+            if text.len() == 2 {
+                return Err(lex_error("Invalid hexadecimal literal", line, column));
+            }
+        } else {
+            self.read_while(&mut text, |c| c.is_digit(10));
+            if self.peek() == Some('.') {
+                text.push('.');
+                self.bump();
+                self.read_while(&mut text, |c| c.is_digit(10));
+            }
+            if self.peek() == Some('e') || self.peek() == Some('E') {
+                text.push(self.bump().unwrap());
+                if self.peek() == Some('+') || self.peek() == Some('-') {
+                    text.push(self.bump().unwrap());
+                }
+                let before = text.len();
+                self.read_while(&mut text, |c| c.is_digit(10));
+                if text.len() == before {
+                    return Err(lex_error("Missing exponent in numeric literal", line, column));
+                }
+            }
+        }
+
+        // ES5 §7.8.3: the source character immediately following a numeric literal must not be
+        // an identifier start or a digit.
+        if self.peek().map_or(false, is_ident_start) {
+            return Err(lex_error("Identifier starts immediately after numeric literal", line, column));
+        }
+        Ok(text)
+    }
+
+    fn read_string(&mut self, line: usize, column: usize) -> js_error::Result<String> {
+        let quote = self.bump().unwrap();
+        let mut text = quote.to_string();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    text.push('\\');
+                    self.bump();
+                    match self.bump() {
+                        Some(c) => {
+                            text.push(c);
+                            // Line continuations may use "\r\n".
+                            if c == '\r' && self.peek() == Some('\n') {
+                                text.push('\n');
+                                self.bump();
+                            }
+                        }
+                        None => return Err(lex_error("Unterminated string literal", line, column)),
+                    }
+                }
+                Some(c) if c == quote => {
+                    text.push(c);
+                    self.bump();
+                    return Ok(text);
+                }
+                Some(c) if !is_line_terminator(c) => {
+                    text.push(c);
+                    self.bump();
+                }
+                _ => return Err(lex_error("Unterminated string literal", line, column)),
+            }
+        }
+    }
+
+    fn read_regex(&mut self, line: usize, column: usize) -> js_error::Result<String> {
+        let mut text = String::new();
+        text.push(self.bump().unwrap());
+        let mut in_class = false;
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    text.push('\\');
+                    self.bump();
+                    match self.peek() {
+                        Some(c) if !is_line_terminator(c) => {
+                            text.push(c);
+                            self.bump();
+                        }
+                        _ => return Err(lex_error("Unterminated regular expression literal", line, column)),
+                    }
+                }
+                Some('/') if !in_class => {
+                    text.push('/');
+                    self.bump();
+                    break;
+                }
+                Some(c) if !is_line_terminator(c) => {
+                    if c == '[' {
+                        in_class = true;
+                    } else if c == ']' {
+                        in_class = false;
+                    }
+                    text.push(c);
+                    self.bump();
+                }
+                _ => return Err(lex_error("Unterminated regular expression literal", line, column)),
+            }
+        }
+        // Flags
+        self.read_while(&mut text, is_ident_part);
+        Ok(text)
+    }
+
+    fn read_punct(&mut self, line: usize, column: usize) -> js_error::Result<String> {
+        for punct in PUNCTUATORS {
+            let matches = punct.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c));
+            if matches {
+                for _ in 0..punct.chars().count() {
+                    self.bump();
+                }
+                return Ok(punct.to_string());
+            }
+        }
+        let c = self.peek().unwrap();
+        Err(lex_error(&format!("Unexpected character '{}'", c), line, column))
+    }
+}
+
+/// Tracks enough of the bracket structure to tell whether a `)` or `}` ends an expression (so a
+/// `/` after it divides) or a statement header or block (so a `/` after it starts a regex).
+struct Context {
+    stack: Vec<Open>,
+    /// Whether the next token is in a position where a statement starts.
+    at_statement_start: bool,
+    /// Set after `function`, until its parameter list opens. `true` for declarations.
+    pending_fn: Option<bool>,
+    /// Set by a `)` closing a parameter list, for the `{` of the function body.
+    after_params: Option<bool>,
+    /// Whether the last token was a `)` closing a control header or a `}` closing a block.
+    closed_statement_part: bool,
+}
+
+impl Context {
+    /// Whether a `/` following `prev` starts a regular expression literal rather than being a
+    /// division operator.
+    fn regex_allowed(&self, prev: Option<&Token>) -> bool {
+        match prev {
+            None => true,
+            Some(t) => match t.kind {
+                TokenKind::Ident | TokenKind::Num | TokenKind::Str | TokenKind::Regex => false,
+                TokenKind::Keyword => t.is_keyword_in(KEYWORDS_BEFORE_EXPRESSION) ||
+                    !t.is_keyword_in(KEYWORDS_ENDING_STATEMENT),
+                TokenKind::Punct if t.is(")") || t.is("}") => self.closed_statement_part,
+                TokenKind::Punct => !(t.is("]") || t.is("++") || t.is("--")),
+            }
+        }
+    }
+
+    fn in_statement_list(&self) -> bool {
+        match self.stack.last() {
+            None | Some(&Open::Brace(BraceKind::Block)) | Some(&Open::Brace(BraceKind::FnBody(_))) => true,
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, t: &Token, prev: Option<&Token>) {
+        let mut at_statement_start = false;
+        let mut closed_statement_part = false;
+        let after_params = self.after_params.take();
+
+        match t.kind {
+            TokenKind::Keyword if t.is("function") => self.pending_fn = Some(self.at_statement_start),
+            TokenKind::Keyword => at_statement_start = t.is_keyword_in(&["do", "else", "finally", "try"]),
+            TokenKind::Punct => match &*t.text {
+                "(" => {
+                    let kind = match self.pending_fn.take() {
+                        Some(is_decl) => ParenKind::Params(is_decl),
+                        None if prev.map_or(false, |p| p.is_keyword_in(CONTROL_KEYWORDS)) => ParenKind::Control,
+                        None => ParenKind::Plain,
+                    };
+                    self.stack.push(Open::Paren(kind));
+                }
+                ")" => match self.stack.pop() {
+                    Some(Open::Paren(ParenKind::Control)) => {
+                        at_statement_start = true;
+                        closed_statement_part = true;
+                    }
+                    Some(Open::Paren(ParenKind::Params(is_decl))) => self.after_params = Some(is_decl),
+                    _ => (),
+                },
+                "[" => self.stack.push(Open::Bracket),
+                "]" => {
+                    self.stack.pop();
+                }
+                "{" => {
+                    let kind = match after_params {
+                        Some(is_decl) => BraceKind::FnBody(is_decl),
+                        None if self.at_statement_start => BraceKind::Block,
+                        None => BraceKind::Object,
+                    };
+                    self.stack.push(Open::Brace(kind));
+                    at_statement_start = kind != BraceKind::Object;
+                }
+                "}" => match self.stack.pop() {
+                    Some(Open::Brace(BraceKind::Object)) | Some(Open::Brace(BraceKind::FnBody(false))) => (),
+                    _ => {
+                        at_statement_start = true;
+                        closed_statement_part = true;
+                    }
+                },
+                ";" => at_statement_start = true,
+                // Labels and `case` clauses are followed by statements, object keys by values.
+                ":" => at_statement_start = self.in_statement_list(),
+                _ => (),
+            },
+            _ => (),
+        }
+
+        if !t.is("function") && t.kind != TokenKind::Ident {
+            self.pending_fn = None;
+        }
+        self.at_statement_start = at_statement_start;
+        self.closed_statement_part = closed_statement_part;
+    }
+}
+
+/// Split source text into tokens, discarding whitespace and comments.
+pub fn tokenize(source: &str) -> js_error::Result<Vec<Token>> {
+    let mut lexer = Lexer { chars: source.chars().collect(), pos: 0, line: 1, column: 1 };
+    let mut context = Context {
+        stack: Vec::new(),
+        at_statement_start: true,
+        pending_fn: None,
+        after_params: None,
+        closed_statement_part: false,
+    };
+    let mut tokens: Vec<Token> = Vec::new();
+
+    loop {
+        let newline_before = try!(lexer.skip_trivia());
+        let c = match lexer.peek() {
+            Some(c) => c,
+            None => return Ok(tokens),
+        };
+        let (line, column) = (lexer.line, lexer.column);
+
+        let (kind, text) = if is_ident_start(c) || c == '\\' {
+            let text = try!(lexer.read_ident(line, column));
+            let kind = if KEYWORDS.contains(&&*text) { TokenKind::Keyword } else { TokenKind::Ident };
+            (kind, text)
+        } else if c.is_digit(10) || (c == '.' && lexer.peek_at(1).map_or(false, |c| c.is_digit(10))) {
+            (TokenKind::Num, try!(lexer.read_number(line, column)))
+        } else if c == '"' || c == '\'' {
+            (TokenKind::Str, try!(lexer.read_string(line, column)))
+        } else if c == '/' && context.regex_allowed(tokens.last()) {
+            (TokenKind::Regex, try!(lexer.read_regex(line, column)))
+        } else {
+            (TokenKind::Punct, try!(lexer.read_punct(line, column)))
+        };
+
+        let token = Token {
+            kind: kind,
+            text: text,
+            line: line,
+            column: column,
+            newline_before: newline_before,
+        };
+        context.push(&token, tokens.last());
+        tokens.push(token);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ParenKind {
+    /// The header of `if`, `while`, `for`, ...
+    Control,
+    /// A function's parameter list. `true` for function declarations.
+    Params(bool),
+    Plain,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BraceKind {
+    Block,
+    Object,
+    /// A function body. `true` for function declarations.
+    FnBody(bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Open {
+    Paren(ParenKind),
+    Bracket,
+    Brace(BraceKind),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn texts(source: &str) -> Vec<String> {
+        tokenize(source).unwrap().into_iter().map(|t| t.text).collect()
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(vec!["var", "a", "=", "\"http://x\"", ";"], texts("var a = \"http://x\"; // comment"));
+        assert_eq!(vec!["a", "b"], texts("a /* { */ b"));
+        assert!(tokenize("a /* b").is_err());
+    }
+
+    #[test]
+    fn test_regex_or_division() {
+        assert_eq!(vec!["a", "/", "b", "/", "c"], texts("a / b / c"));
+        assert_eq!(vec!["x", "=", "/}\\//g", ";"], texts("x = /}\\//g;"));
+        assert_eq!(vec!["(", "a", ")", "/", "2"], texts("(a) / 2"));
+        assert_eq!(vec!["var", "f", "=", "function", "(", ")", "{", "}", "/", "2"], texts("var f = function() {} / 2"));
+        assert_eq!(vec!["x", "=", "{", "}", "/", "2"], texts("x = {} / 2"));
+    }
+
+    #[test]
+    fn test_regex_after_statement() {
+        // A `)` closing a control header or a `}` closing a block ends no expression.
+        assert_eq!(vec!["if", "(", "a", ")", "/}/", ".", "test", "(", "s", ")"], texts("if (a) /}/.test(s)"));
+        assert_eq!(vec!["while", "(", "a", ")", "{", "}", "/b/", ".", "exec", "(", "s", ")"],
+                   texts("while (a) {} /b/.exec(s)"));
+        assert_eq!(vec!["function", "f", "(", ")", "{", "}", "/x/g", ".", "test", "(", "s", ")"],
+                   texts("function f() {}\n/x/g.test(s)"));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(vec!["0x1F", "1.5e-3", ".5", "'it\\'s'"], texts("0x1F 1.5e-3 .5 'it\\'s'"));
+        assert!(tokenize("\"abc").is_err());
+        assert!(tokenize("3in x").is_err());
+        assert_eq!(vec!["ab"], texts("\\u0061b"));
+    }
+
+    #[test]
+    fn test_positions() {
+        let tokens = tokenize("a\n  /* x\n */ b").unwrap();
+        assert_eq!((1, 1, false), (tokens[0].line, tokens[0].column, tokens[0].newline_before));
+        assert_eq!((3, 5, true), (tokens[1].line, tokens[1].column, tokens[1].newline_before));
+    }
+}
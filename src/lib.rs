@@ -0,0 +1,200 @@
+#![feature(test)]
+
+extern crate jsrs_common;
+extern crate french_press;
+
+extern crate unescape;
+
+extern crate test;
+
+mod var;
+
+mod ast;
+mod eval;
+mod native;
+mod number;
+mod bench;
+mod lexer;
+mod parser;
+mod program;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
+
+use french_press::{init_gc, ScopeManager};
+use jsrs_common::js_error;
+
+use eval::{drop_bodies, eval_program, to_string};
+
+pub use native::NativeFnPtr;
+pub use program::Program;
+pub use var::{JsVarValue, scalar, scalar_to_string};
+
+// The types native functions and `JsVarValue`s are built from.
+pub use jsrs_common::backend::Backend;
+pub use jsrs_common::js_error::{JsError, Result};
+pub use jsrs_common::types::binding::Binding;
+pub use jsrs_common::types::js_var::{JsKey, JsPtrEnum, JsPtrTag, JsType, JsVar};
+
+
+/// A JavaScript execution context. Each `Engine` owns its own scope manager, so globals
+/// defined in one engine are not visible from another.
+pub struct Engine {
+    state: Rc<RefCell<ScopeManager>>,
+    debug: bool,
+}
+
+impl Engine {
+    /// Creates an engine with the standard pervasives (`log`, `Array`, `Number`, ...) defined.
+    pub fn new() -> Engine {
+        let state = Rc::new(RefCell::new(init_gc()));
+        native::add_pervasives(state.clone());
+        Engine { state: state, debug: false }
+    }
+
+    /// When set, `eval_file` prints the path it reads, "Parsed N statements" once the file has
+    /// parsed, and the script's final result.
+    pub fn debug(mut self, debug: bool) -> Engine {
+        self.debug = debug;
+        self
+    }
+
+    /// Evaluate a string containing some JavaScript statements, returning the value of the
+    /// last one. Nothing is evaluated if the string fails to parse.
+    pub fn eval(&self, source: &str) -> js_error::Result<JsVarValue> {
+        let program = try!(Program::parse(source));
+        eval_program(&program, self.state.clone())
+    }
+
+    /// Evaluate a JavaScript file. The whole file is parsed before any of it is evaluated. A file
+    /// which can't be read is an `io::Error`, while the script's own errors are `JsError`s.
+    pub fn eval_file<P: AsRef<Path>>(&self, path: P) -> io::Result<js_error::Result<JsVarValue>> {
+        let path = path.as_ref();
+        if self.debug {
+            println!("Reading from \"{}\"", path.display());
+        }
+
+        let mut source = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut source)));
+        Ok(self.eval_source(&source))
+    }
+
+    /// Evaluate the source of a file, with the `debug` output.
+    fn eval_source(&self, source: &str) -> js_error::Result<JsVarValue> {
+        let program = try!(Program::parse(source));
+        if self.debug {
+            println!("Parsed {} statements", program.body.len());
+        }
+
+        let ret = try!(eval_program(&program, self.state.clone()));
+        if self.debug {
+            println!("=> {:?}", ret);
+        }
+        Ok(ret)
+    }
+
+    /// Look up a variable in the global scope.
+    pub fn get_global(&self, name: &str) -> Option<JsVarValue> {
+        self.state.borrow_mut().load(&Binding::new(name.to_owned())).ok()
+    }
+
+    /// Bind `name` to `value` in the global scope, shadowing any existing binding.
+    pub fn set_global(&self, name: &str, value: JsVarValue) -> js_error::Result<()> {
+        let (mut var, ptr) = value;
+        var.binding = Binding::new(name.to_owned());
+        try!(self.state.borrow_mut().alloc(var, ptr));
+        Ok(())
+    }
+
+    /// Convert a value to a string the way scripts do, calling `toString` on objects.
+    pub fn to_string(&self, value: JsVarValue) -> js_error::Result<String> {
+        to_string(value, self.state.clone())
+    }
+
+    /// Expose a Rust function to scripts as a global function called `name`.
+    pub fn register_native_fn(&self, name: &str, func: NativeFnPtr) -> js_error::Result<()> {
+        native::register_native_fn(self.state.clone(), name, func)
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        drop_bodies(&self.state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn double(_state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>, args: Vec<JsVarValue>)
+              -> Result<JsVarValue> {
+        match args.first() {
+            Some(&(JsVar { t: JsType::JsNum(n), .. }, _)) => Ok(scalar(JsType::JsNum(n * 2.0))),
+            _ => Ok(scalar(JsType::JsUndef)),
+        }
+    }
+
+    #[test]
+    fn test_register_native_fn() {
+        let engine = Engine::new();
+        engine.register_native_fn("double", double).unwrap();
+        assert_eq!(JsType::JsNum(42.0), engine.eval("double(21);\n").unwrap().0.t);
+    }
+
+    #[test]
+    fn test_globals() {
+        let engine = Engine::new();
+        engine.set_global("answer", scalar(JsType::JsNum(42.0))).unwrap();
+        assert_eq!(JsType::JsNum(42.0), engine.get_global("answer").unwrap().0.t);
+        assert_eq!(JsType::JsNum(43.0), engine.eval("answer + 1;\n").unwrap().0.t);
+
+        engine.eval("var greeting = 'hi';\n").unwrap();
+        assert_eq!(JsType::JsNum(2.0), engine.eval("greeting.length;\n").unwrap().0.t);
+        assert!(engine.get_global("greeting").is_some());
+        assert!(engine.get_global("missing").is_none());
+    }
+
+    #[test]
+    fn test_to_string() {
+        let engine = Engine::new();
+        let value = engine.eval("[1e21, 'a'];\n").unwrap();
+        assert_eq!("1e+21,a", engine.to_string(value).unwrap());
+        let value = engine.eval("({ toString: function() { return 'custom'; } });\n").unwrap();
+        assert_eq!("custom", engine.to_string(value).unwrap());
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let engine = Engine::new();
+        assert!(engine.eval_file("no/such/file.js").is_err());
+    }
+
+    #[test]
+    fn test_engines_are_isolated() {
+        let first = Engine::new();
+        let second = Engine::new();
+        first.eval("var x = 1;\n").unwrap();
+        assert!(first.get_global("x").is_some());
+        assert!(second.get_global("x").is_none());
+        assert!(second.eval("x;\n").is_err());
+
+        first.eval("function f() { return 1; }\n").unwrap();
+        second.eval("function g() { return 2; }\n").unwrap();
+        assert_eq!(JsType::JsNum(1.0), first.eval("f();\n").unwrap().0.t);
+        assert_eq!(JsType::JsNum(2.0), second.eval("g();\n").unwrap().0.t);
+    }
+}
@@ -1,48 +1,23 @@
 #![feature(plugin)]
 #![plugin(docopt_macros)]
 
-#![feature(test)]
-
-extern crate jsrs_common;
-extern crate jsrs_parser;
-extern crate french_press;
+extern crate jsrs;
 
 extern crate walkdir;
-extern crate unescape;
 extern crate rustyline;
 extern crate rustc_serialize;
 extern crate docopt;
 
-extern crate test;
-
-#[macro_use]
-mod var;
-
-mod eval;
-mod native;
-mod number;
-mod bench;
-mod preprocess;
-
-use std::cell::RefCell;
 use std::fs::{File, metadata};
-use std::io::{self, BufReader};
+use std::io;
 use std::io::prelude::*;
-use std::path::Path;
 use std::process::exit;
-use std::rc::Rc;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use walkdir::WalkDir;
 
-use jsrs_common::types::coerce::AsString;
-use french_press::{init_gc, ScopeManager};
-
-use eval::eval_string;
-use native::add_pervasives;
-use preprocess::{clean_string, add_semicolon};
-use jsrs_common::js_error::{self, JsError};
+use jsrs::Engine;
 
 
 docopt!(Args derive Debug, "
@@ -54,125 +29,12 @@ jsrs <file>
 jsrs --test
 ");
 
-fn eval_file(filename: String, debug: bool, should_repl: bool,
-             scope_manager: Rc<RefCell<ScopeManager>>) -> js_error::Result<()> {
-    if debug {
-        println!("Reading from \"{}\"", filename);
+fn is_negative_test(filename: &str) -> bool {
+    let mut contents = String::new();
+    match File::open(filename).map(|mut f| f.read_to_string(&mut contents)) {
+        Ok(Ok(_)) => contents.contains("@negative"),
+        _ => false,
     }
-
-    let path = Path::new(&filename);
-    let file = File::open(&path)
-        .expect(&format!("Cannot open \"{}\": no such file or directory", filename));
-    let file_buffer = BufReader::new(file);
-
-    // Build lines
-    let mut line_builder = String::new();
-    let mut braces = Vec::new();
-    let mut negative_test = false;
-
-    let mut file_iter = file_buffer.lines();
-    while let Some(line) = file_iter.next() {
-        let input = String::from(line.expect(&format!("Cannot read from {}", filename)));
-        let input = clean_string(input);
-        if input.contains("@negative") {
-            negative_test = true;
-        }
-
-        let mut last = '\0';
-        // Match braces to see if we should wait for more input
-        for c in input.chars() {
-            if c == '(' {
-                braces.push('(');
-            } else if c == '{' {
-                braces.push('{');
-            } else if c == '*' && last == '/' {
-                braces.push('/');
-            } else if c == ')' {
-                if braces.pop() != Some('(') {
-                    return Err(JsError::ParseError(format!("Unexpected token {}", c)));
-                }
-            } else if c == '}' {
-                if braces.pop() != Some('{') {
-                    return Err(JsError::ParseError(format!("Unexpected token {}", c)));
-                }
-            } else if c == '/' && last == '*' {
-                if braces.pop() != Some('/') {
-                    return Err(JsError::ParseError(format!("Unexpected token {}", c)));
-                }
-            }
-            last = c;
-        }
-
-        line_builder.push_str(&input);
-
-        if braces.len() == 0 {
-            let js_string = clean_string(line_builder.clone());
-            if js_string == "" {
-                continue;
-            }
-
-            if debug {
-                println!("\n{}", js_string);
-            }
-
-            let ret;
-            let eval_result = eval_string(&js_string, scope_manager.clone());
-            if let Err(JsError::ParseError(_)) = eval_result {
-                line_builder.push_str(" ");
-                continue;
-            }
-
-            if negative_test {
-                match eval_result {
-                    Ok((var, ptr)) => ret = (var, ptr),
-                    Err(e) => {
-                        if !e.is_meta_error() {
-                            return Ok(());
-                        } else {
-                            return Err(e);
-                        }
-                    }
-                }
-            } else {
-                ret = try!(eval_result);
-            }
-
-            if debug {
-                println!("=> {:?}", ret);
-            }
-
-            // Clear line builder for next statement.
-            line_builder = String::new();
-        }
-    }
-    if line_builder != "" {
-        let ret;
-        let js_string = clean_string(line_builder.clone());
-        let eval_result = eval_string(&js_string, scope_manager.clone());
-
-        if negative_test {
-            match eval_result {
-                Ok((var, ptr)) => ret = (var, ptr),
-                Err(e) => {
-                    if !e.is_meta_error() {
-                        return Ok(());
-                    } else {
-                        return Err(e);
-                    }
-                }
-            }
-        } else {
-            ret = try!(eval_result);
-        }
-
-        if debug {
-            println!("=> {:?}", ret);
-        }
-    }
-    if should_repl {
-        repl(scope_manager);
-    }
-    Ok(())
 }
 
 fn test_dir(dir_name: String) {
@@ -180,10 +42,17 @@ fn test_dir(dir_name: String) {
         let entry = entry.unwrap();
         if !entry.path().is_dir() {
             let entry_path = entry.path().display().to_string();
-            let scope_manager = Rc::new(RefCell::new(init_gc()));
-            add_pervasives(scope_manager.clone());
+            let engine = Engine::new();
             println!("file: {}", entry_path.clone());
-            match eval_file(entry_path.clone(), false, false, scope_manager.clone()) {
+            let result = match engine.eval_file(&entry_path) {
+                // Negative tests pass as long as they fail for a reason other than the harness.
+                Ok(Err(ref e)) if is_negative_test(&entry_path) && !e.is_meta_error() => Ok(()),
+                Ok(Err(e)) => Err(e.to_string()),
+                Ok(Ok(_)) => Ok(()),
+                // An unreadable file fails, even as a negative test.
+                Err(e) => Err(format!("Cannot read file: {}", e)),
+            };
+            match result {
                 Ok(_) => {
                     println!("{}: {}", entry_path, "OK");
                 }
@@ -195,7 +64,7 @@ fn test_dir(dir_name: String) {
     }
 }
 
-fn repl(scope_manager: Rc<RefCell<ScopeManager>>) -> i32 {
+fn repl(engine: &Engine) -> i32 {
     let mut rl = Editor::new();
     let mut stderr = io::stderr();
 
@@ -210,14 +79,15 @@ fn repl(scope_manager: Rc<RefCell<ScopeManager>>) -> i32 {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(&line);
-                let input = add_semicolon(clean_string(String::from(line)));
-                if input == "" {
+                if line.trim() == "" {
                     continue;
                 }
-                rl.add_history_entry(&input);
 
-                match eval_string(&input, scope_manager.clone()) {
-                    Ok((var, ptr)) => println!("{}", ptr.map(|p| p.as_string()).unwrap_or(var.t.as_string())),
+                match engine.eval(&line) {
+                    Ok(value) => match engine.to_string(value) {
+                        Ok(string) => println!("{}", string),
+                        Err(e) => println!("{:?}", e),
+                    },
                     Err(e) => println!("{:?}", e),
                 }
             },
@@ -255,16 +125,12 @@ fn main() {
         let dir_name = "sputnik";
 
         test_dir(String::from(dir_name))
+    } else if args.arg_file == "" {
+        let ret = repl(&Engine::new());
+        exit(ret)
     } else {
-        let scope_manager = Rc::new(RefCell::new(init_gc()));
-        add_pervasives(scope_manager.clone());
-
-        if args.arg_file == "" {
-            let ret = repl(scope_manager.clone());
-            exit(ret)
-        } else {
-            eval_file(args.arg_file, true, true, scope_manager.clone())
-                .expect("Error evaluating file");
-        }
+        let engine = Engine::new().debug(true);
+        engine.eval_file(&args.arg_file).expect("Error reading file").expect("Error evaluating file");
+        repl(&engine);
     }
 }
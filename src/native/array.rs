@@ -1,7 +1,8 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use var::{js_str_key, JsVarValue};
+use number::{number_to_string, ptr_to_number};
+use var::{js_str_key, scalar_to_string, JsVarValue};
 
 use jsrs_common::backend::Backend;
 use jsrs_common::types::coerce::{AsNumber, AsString};
@@ -11,7 +12,7 @@ use jsrs_common::js_error;
 
 fn var_type_as_number(var: &JsVar, ptr: Option<&JsPtrEnum>) -> f64 {
     match ptr {
-        Some(ref ptr) => ptr.as_number(),
+        Some(ref ptr) => ptr_to_number(ptr),
         None => var.as_number()
     }
 }
@@ -54,7 +55,7 @@ pub fn array_to_string(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEn
             out.push_str(",");
         }
 
-        let key = JsKey::JsStr(JsStrStruct::new(&JsType::JsNum(i as f64).as_string()));
+        let key = JsKey::JsStr(JsStrStruct::new(&number_to_string(i as f64)));
         let var = match this_obj.dict.get(&key) {
             Some(var) => var,
             None => continue,
@@ -65,13 +66,13 @@ pub fn array_to_string(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEn
                 &JsPtrEnum::JsObj(ref obj) if obj.proto.is_some() && obj.name == "array" => {
                     let o_this = Some((var.clone(), JsPtrEnum::JsObj(obj.clone())));
                     let (o_var, o_ptr) = try!(array_to_string(state.clone(), o_this, Vec::new()));
-                    o_ptr.map(|p| p.as_string()).unwrap_or(o_var.t.as_string())
+                    o_ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&o_var.t))
                 }
                 ref ptr => ptr.as_string(),
             },
             None => match &var.t {
                 &JsType::JsUndef => String::new(),
-                ref t => t.as_string(),
+                ref t => scalar_to_string(t),
             }
         };
         out.push_str(&s);
@@ -118,7 +119,7 @@ pub fn array_push(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
     let alloc_box = state_ref.get_alloc_box();
 
     for (i, (var, ptr)) in args.into_iter().enumerate() {
-        let key = JsKey::JsStr(JsStrStruct::new(&JsType::JsNum(length + i as f64).as_string()));
+        let key = JsKey::JsStr(JsStrStruct::new(&number_to_string(length + i as f64)));
         this_obj.add_key(&this_var.unique, key, var, ptr, &mut *(alloc_box.borrow_mut()));
     }
 
@@ -148,7 +149,7 @@ pub fn array_length_setter(state: Rc<RefCell<Backend>>, old_var: JsVar, old_ptr:
 
     if new_len > old_len {
         for i in old_len_int..new_len_int {
-            let key = JsKey::JsStr(JsStrStruct::new(&JsType::JsNum(i as f64).as_string()));
+            let key = JsKey::JsStr(JsStrStruct::new(&number_to_string(i as f64)));
             let alloc_box = state_ref.get_alloc_box();
             this_obj.add_key(&this_var.unique, key, JsVar::new(JsType::JsUndef), None, &mut *(alloc_box.borrow_mut()));
         }
@@ -156,7 +157,7 @@ pub fn array_length_setter(state: Rc<RefCell<Backend>>, old_var: JsVar, old_ptr:
 
     if new_len < old_len {
         for i in new_len_int..old_len_int {
-            let key = js_str_key(&JsType::JsNum(i as f64).as_string());
+            let key = js_str_key(&number_to_string(i as f64));
             let alloc_box = state_ref.get_alloc_box();
             this_obj.remove_key(&this_var.unique, &key, &mut *(alloc_box.borrow_mut()));
         }
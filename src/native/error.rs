@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use var::{find_property, js_str_key, scalar_to_string, JsVarValue};
+
+use jsrs_common::backend::Backend;
+use jsrs_common::js_error::{self, JsError};
+use jsrs_common::types::coerce::AsString;
+use jsrs_common::types::js_obj::JsObjStruct;
+use jsrs_common::types::js_str::JsStrStruct;
+use jsrs_common::types::js_var::{JsPtrEnum, JsPtrTag, JsType, JsVar};
+
+use super::get_native_proto;
+
+/// Builds an instance of the native error class `class` (`"Error"`, `"TypeError"`, ...).
+pub fn new_error(state: Rc<RefCell<Backend>>, class: &str, message: Option<String>) -> JsVarValue {
+    let mut kv_tuples = Vec::new();
+    if let Some(message) = message {
+        let var = JsVar::new(JsType::JsPtr(JsPtrTag::JsStr));
+        kv_tuples.push((js_str_key("message"), var, Some(JsPtrEnum::JsStr(JsStrStruct::new(&message)))));
+    }
+
+    let proto = get_native_proto(class, state.clone()).map(Box::new);
+    let state_ref = state.borrow_mut();
+    let alloc_box = state_ref.get_alloc_box();
+    let obj = JsObjStruct::new(proto, "Error", kv_tuples, &mut *(alloc_box.borrow_mut()));
+
+    (JsVar::new(JsType::JsPtr(JsPtrTag::JsObj)), Some(JsPtrEnum::JsObj(obj)))
+}
+
+/// Converts an error raised while evaluating into the value a `catch` clause sees: thrown values
+/// are passed through, and errors raised by the engine itself become instances of the matching
+/// native error class. Meta errors (failed tests, GC failures, ...) are not catchable, and are
+/// handed back unchanged.
+pub fn from_js_error(state: Rc<RefCell<Backend>>, e: JsError) -> Result<JsVarValue, JsError> {
+    let (class, message) = match e {
+        JsError::JsVar(value) => return Ok(value),
+        JsError::TypeError(message) => ("TypeError", message),
+        JsError::ReferenceError(message) => ("ReferenceError", message),
+        JsError::SyntaxError(message) => ("SyntaxError", message),
+        JsError::RangeError(message) => ("RangeError", message),
+        e => return Err(e),
+    };
+    Ok(new_error(state, class, Some(message)))
+}
+
+fn message_arg(args: &Vec<(JsVar, Option<JsPtrEnum>)>) -> Option<String> {
+    match args.first() {
+        Some(&(_, Some(ref ptr))) => Some(ptr.as_string()),
+        Some(&(ref var, None)) if var.t != JsType::JsUndef => Some(scalar_to_string(&var.t)),
+        _ => None,
+    }
+}
+
+pub fn error(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
+             args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    Ok(new_error(state, "Error", message_arg(&args)))
+}
+
+pub fn type_error(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
+                  args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    Ok(new_error(state, "TypeError", message_arg(&args)))
+}
+
+pub fn reference_error(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
+                       args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    Ok(new_error(state, "ReferenceError", message_arg(&args)))
+}
+
+pub fn syntax_error(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
+                    args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    Ok(new_error(state, "SyntaxError", message_arg(&args)))
+}
+
+pub fn range_error(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
+                   args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    Ok(new_error(state, "RangeError", message_arg(&args)))
+}
+
+/// Reads a property of `obj` (or its prototypes) as a string.
+fn string_property(state: Rc<RefCell<Backend>>, obj: &JsObjStruct, key: &str) -> Option<String> {
+    let var = match find_property(obj, &js_str_key(key), state.clone()) {
+        Some(var) => var,
+        None => return None,
+    };
+
+    let state_ref = state.borrow_mut();
+    let alloc_box = state_ref.get_alloc_box();
+    let ptr = alloc_box.borrow_mut().find_id(&var.unique).map(|p| p.borrow().clone());
+    match ptr {
+        Some(ptr) => Some(ptr.as_string()),
+        None => match var.t {
+            JsType::JsUndef => None,
+            ref t => Some(scalar_to_string(t)),
+        }
+    }
+}
+
+pub fn error_to_string(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                       _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let obj = match this {
+        Some((_, JsPtrEnum::JsObj(obj))) => obj,
+        _ => return Err(JsError::TypeError(String::from("Error.prototype.toString called on non-object"))),
+    };
+
+    let name = string_property(state.clone(), &obj, "name").unwrap_or(String::from("Error"));
+    let message = string_property(state.clone(), &obj, "message").unwrap_or(String::new());
+
+    let s = if name.is_empty() {
+        message
+    } else if message.is_empty() {
+        name
+    } else {
+        format!("{}: {}", name, message)
+    };
+
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&s)))))
+}
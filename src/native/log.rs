@@ -1,17 +1,20 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use native::caller_to_string;
+use var::scalar_to_string;
+
 use jsrs_common::backend::Backend;
 use jsrs_common::types::coerce::AsString;
 use jsrs_common::types::js_var::{JsType, JsPtrEnum, JsVar};
 
 use jsrs_common::js_error::{self, JsError};
 
+/// Print the first argument, converted to a string the way scripts do.
 pub fn log(_scope: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
        args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
-    match args.first() {
-        Some(&(_, Some(ref var))) => println!("{}", var.as_string()),
-        Some(&(ref var, _)) => println!("{}", var.t.as_string()),
+    match args.into_iter().next() {
+        Some(arg) => println!("{}", try!(caller_to_string(arg))),
         None => println!("")
     };
 
@@ -23,7 +26,7 @@ pub fn error(_scope: Rc<RefCell<Backend>>,
             args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
     let s = match args.first() {
         Some(&(_, Some(ref var))) => var.as_string(),
-        Some(&(ref var, _)) => var.t.as_string(),
+        Some(&(ref var, _)) => scalar_to_string(&var.t),
         None => String::from(""),
     };
 
@@ -1,20 +1,35 @@
 mod array;
+mod error;
 mod log;
+mod object;
 mod stdlib;
 mod types;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use eval::to_string;
 use var::{js_str_key, JsVarValue, scalar};
 
+pub use self::error::from_js_error;
+pub use self::types::to_object;
+
 use french_press::ScopeManager;
 use jsrs_common::backend::Backend;
-use jsrs_common::types::js_var::{JsType, JsPtrEnum, JsPtrTag, JsVar};
+use jsrs_common::js_error::{self, JsError};
+use jsrs_common::types::binding::Binding;
+use jsrs_common::types::js_var::{JsKey, JsType, JsPtrEnum, JsPtrTag, JsVar};
 use jsrs_common::types::js_obj::JsObjStruct;
+use jsrs_common::types::js_str::JsStrStruct;
 use jsrs_common::types::native_fn::NativeFn;
 use jsrs_common::types::native_var::NativeVar;
 
+/// Unique binding the global object is allocated under, so that it can be found from any scope.
+const GLOBAL_OBJECT_BINDING: &'static str = "%global";
+
+/// The signature every native function exposed to JavaScript must have.
+pub type NativeFnPtr = fn(Rc<RefCell<Backend>>, Option<(JsVar, JsPtrEnum)>, Vec<JsVarValue>)
+                          -> js_error::Result<JsVarValue>;
 
 macro_rules! add_pervasive {
     ($var:expr, $ptr:expr, $st:expr, $name:expr) => {
@@ -41,6 +56,10 @@ macro_rules! add_native_var {
 }
 
 pub fn add_pervasives(state: Rc<RefCell<ScopeManager>>) {
+    add_object_proto(state.clone());
+    add_wrapper_protos(state.clone());
+    add_error_protos(state.clone());
+    add_global_object(state.clone());
     add_native_fn!(log::log, state, "log");
     add_native_fn!(log::error, state, "$ERROR");
     add_native_fn!(types::boolean, state, "Boolean");
@@ -48,10 +67,229 @@ pub fn add_pervasives(state: Rc<RefCell<ScopeManager>>) {
     add_native_fn!(types::string, state, "String");
     add_native_fn!(types::object, state, "Object");
 
+    add_native_fn!(error::error, state, "Error");
+    add_native_fn!(error::type_error, state, "TypeError");
+    add_native_fn!(error::reference_error, state, "ReferenceError");
+    add_native_fn!(error::syntax_error, state, "SyntaxError");
+    add_native_fn!(error::range_error, state, "RangeError");
+
     add_native_fn!(stdlib::is_nan, state, "isNaN");
     add_array(state)
 }
 
+thread_local! {
+    /// The scope managers of the scripts running native functions, innermost last.
+    static CALLERS: RefCell<Vec<Rc<RefCell<ScopeManager>>>> = RefCell::new(Vec::new());
+}
+
+/// Call a native function from a script. While it runs, it can convert values through the
+/// script's scope manager with `caller_to_string`.
+pub fn call_native(func: &NativeFn, state: Rc<RefCell<ScopeManager>>, this: Option<(JsVar, JsPtrEnum)>,
+                   args: Vec<JsVarValue>) -> js_error::Result<JsVarValue> {
+    CALLERS.with(|callers| callers.borrow_mut().push(state.clone()));
+    let result = func.call(state, this, args);
+    CALLERS.with(|callers| callers.borrow_mut().pop());
+    result
+}
+
+/// Convert a value to a string the way scripts do, calling `toString` on objects. Natives can't
+/// call back into scripts themselves, so this goes through the scope manager of the script which
+/// called the running native.
+pub fn caller_to_string(value: JsVarValue) -> js_error::Result<String> {
+    match CALLERS.with(|callers| callers.borrow().last().cloned()) {
+        Some(state) => to_string(value, state),
+        None => Err(JsError::UnimplementedError(String::from("No script is calling a native, native/mod.rs"))),
+    }
+}
+
+pub fn register_native_fn(state: Rc<RefCell<ScopeManager>>, name: &str, func: NativeFnPtr) -> js_error::Result<()> {
+    let ptr_tag = JsType::JsPtr(JsPtrTag::NativeFn { name: String::from(name) });
+    let var = JsVar::bind(name, ptr_tag);
+    let ptr = Some(JsPtrEnum::NativeFn(NativeFn::new(func)));
+    try!(state.borrow_mut().alloc(var, ptr));
+    Ok(())
+}
+
+/// Returns the global object, which is what `this` refers to outside of method calls.
+pub fn get_global_object(state: Rc<RefCell<ScopeManager>>) -> JsVarValue {
+    let mut var = JsVar::new(JsType::JsPtr(JsPtrTag::JsObj));
+    var.unique = Binding::new(String::from(GLOBAL_OBJECT_BINDING));
+    let state_ref = state.borrow_mut();
+    let ptr = state_ref.alloc_box.borrow_mut().find_id(&var.unique).map(|p| p.borrow().clone());
+    (var, ptr)
+}
+
+/// Unique binding the `prototype` object of the constructor `owner` is allocated under.
+fn prototype_binding(owner: &str) -> Binding {
+    Binding::new(format!("{}%prototype", owner))
+}
+
+/// Unique binding a user-defined function's `prototype` object is allocated under. The same string
+/// is used as the prototype's `name`, which is how `instanceof` recognizes it in a prototype chain.
+pub fn fn_prototype_binding(fn_var: &JsVar) -> Binding {
+    prototype_binding(&fn_var.unique.0)
+}
+
+/// Returns the prototype of the native constructor `name`, e.g. `Object.prototype`.
+pub fn get_native_proto(name: &str, state: Rc<RefCell<Backend>>) -> Option<JsObjStruct> {
+    let state_ref = state.borrow_mut();
+    let alloc_box = state_ref.get_alloc_box();
+    let proto = alloc_box.borrow_mut().find_id(&prototype_binding(name)).map(|p| p.borrow().clone());
+    match proto {
+        Some(JsPtrEnum::JsObj(obj)) => Some(obj),
+        _ => None,
+    }
+}
+
+/// Returns the prototype of `obj` as it is now. Objects hold a copy of their prototype, so the
+/// prototypes of native constructors and user-defined functions, which are allocated under a known
+/// binding, are looked up again to see properties added since `obj` was created.
+pub fn get_proto(obj: &JsObjStruct, state: Rc<RefCell<Backend>>) -> Option<JsObjStruct> {
+    let proto = match obj.proto {
+        Some(ref proto) => proto,
+        None => return None,
+    };
+    // User-defined functions' prototypes are named after their binding (see `fn_prototype_binding`).
+    let binding = if proto.name.contains('%') {
+        Binding::new(proto.name.clone())
+    } else {
+        prototype_binding(&proto.name)
+    };
+
+    let state_ref = state.borrow_mut();
+    let alloc_box = state_ref.get_alloc_box();
+    let current = alloc_box.borrow_mut().find_id(&binding).map(|p| p.borrow().clone());
+    match current {
+        Some(JsPtrEnum::JsObj(current)) => Some(current),
+        _ => Some((**proto).clone()),
+    }
+}
+
+/// Like `get_native_proto`, but returns the prototype as a value that can be assigned to, so that
+/// scripts can extend e.g. `Object.prototype`.
+pub fn get_native_proto_var(name: &str, state: Rc<RefCell<Backend>>) -> JsVarValue {
+    let mut var = JsVar::new(JsType::JsPtr(JsPtrTag::JsObj));
+    var.unique = prototype_binding(name);
+    match get_native_proto(name, state) {
+        Some(obj) => (var, Some(JsPtrEnum::JsObj(obj))),
+        None => scalar(JsType::JsUndef),
+    }
+}
+
+/// Allocates the prototype of the native constructor `name`, with the given properties.
+fn add_native_proto(name: &str, proto: Option<JsObjStruct>, kv_tuples: Vec<(JsKey, JsVar, Option<JsPtrEnum>)>,
+                    state: Rc<RefCell<ScopeManager>>) {
+    let mut var = JsVar::new(JsType::JsPtr(JsPtrTag::JsObj));
+    var.unique = prototype_binding(name);
+
+    let ptr = {
+        let state_ref = state.borrow_mut();
+        let obj = JsObjStruct::new(proto.map(Box::new), name, kv_tuples, &mut *(state_ref.alloc_box.borrow_mut()));
+        Some(JsPtrEnum::JsObj(obj))
+    };
+    add_pervasive!(var, ptr, state, format!("{}.prototype", name));
+}
+
+fn native_method(name: &str, func: NativeFnPtr) -> (JsKey, JsVar, Option<JsPtrEnum>) {
+    let var = JsVar::new(JsType::JsPtr(JsPtrTag::NativeFn { name: String::from(name) }));
+    (js_str_key(name), var, Some(JsPtrEnum::NativeFn(NativeFn::new(func))))
+}
+
+fn native_string(name: &str, value: &str) -> (JsKey, JsVar, Option<JsPtrEnum>) {
+    let var = JsVar::new(JsType::JsPtr(JsPtrTag::JsStr));
+    (js_str_key(name), var, Some(JsPtrEnum::JsStr(JsStrStruct::new(value))))
+}
+
+fn add_object_proto(state: Rc<RefCell<ScopeManager>>) {
+    add_native_proto("Object", None, vec![
+        native_method("hasOwnProperty", object::has_own_property),
+        native_method("toString", object::to_string),
+        native_method("valueOf", object::value_of),
+    ], state);
+}
+
+/// Returns the `prototype` object of a user-defined function, creating an empty one the first time
+/// it is asked for.
+pub fn get_fn_prototype(fn_var: &JsVar, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let mut var = JsVar::new(JsType::JsPtr(JsPtrTag::JsObj));
+    var.unique = fn_prototype_binding(fn_var);
+
+    let ptr = {
+        let state_ref = state.borrow_mut();
+        let ptr = state_ref.alloc_box.borrow_mut().find_id(&var.unique).map(|p| p.borrow().clone());
+        ptr
+    };
+    if ptr.is_some() {
+        return Ok((var, ptr));
+    }
+
+    let proto = get_native_proto("Object", state.clone()).map(Box::new);
+    let ptr = {
+        let state_ref = state.borrow_mut();
+        let obj = JsObjStruct::new(proto, &var.unique.0, Vec::new(), &mut *(state_ref.alloc_box.borrow_mut()));
+        Some(JsPtrEnum::JsObj(obj))
+    };
+    try!(state.borrow_mut().alloc(var.clone(), ptr.clone()));
+    Ok((var, ptr))
+}
+
+/// Replaces the `prototype` object of a user-defined function. Non-objects are ignored, as
+/// constructors fall back to a plain object prototype for them anyway.
+pub fn set_fn_prototype(fn_var: &JsVar, proto: Option<JsPtrEnum>, state: Rc<RefCell<ScopeManager>>)
+                        -> js_error::Result<()> {
+    if let Some(JsPtrEnum::JsObj(mut obj)) = proto {
+        let mut var = JsVar::new(JsType::JsPtr(JsPtrTag::JsObj));
+        var.unique = fn_prototype_binding(fn_var);
+        obj.name = var.unique.0.clone();
+        try!(state.borrow_mut().alloc(var, Some(JsPtrEnum::JsObj(obj))));
+    }
+    Ok(())
+}
+
+fn add_wrapper_protos(state: Rc<RefCell<ScopeManager>>) {
+    add_native_proto("String", get_native_proto("Object", state.clone()), vec![
+        native_method("toString", types::string_to_string),
+        native_method("valueOf", types::string_to_string),
+        native_method("charAt", types::string_char_at),
+    ], state.clone());
+    add_native_proto("Number", get_native_proto("Object", state.clone()), vec![
+        native_method("toString", types::number_to_string),
+        native_method("valueOf", types::number_value_of),
+        native_method("toFixed", types::number_to_fixed),
+    ], state.clone());
+    add_native_proto("Boolean", get_native_proto("Object", state.clone()), vec![
+        native_method("toString", types::boolean_to_string),
+        native_method("valueOf", types::boolean_value_of),
+    ], state);
+}
+
+fn add_error_protos(state: Rc<RefCell<ScopeManager>>) {
+    add_native_proto("Error", get_native_proto("Object", state.clone()), vec![
+        native_string("name", "Error"),
+        native_string("message", ""),
+        native_method("toString", error::error_to_string),
+    ], state.clone());
+
+    for class in &["TypeError", "ReferenceError", "SyntaxError", "RangeError"] {
+        add_native_proto(class, get_native_proto("Error", state.clone()), vec![
+            native_string("name", class),
+            native_string("message", ""),
+        ], state.clone());
+    }
+}
+
+fn add_global_object(state: Rc<RefCell<ScopeManager>>) {
+    let mut var = JsVar::bind("this", JsType::JsPtr(JsPtrTag::JsObj));
+    var.unique = Binding::new(String::from(GLOBAL_OBJECT_BINDING));
+    let proto = get_native_proto("Object", state.clone()).map(Box::new);
+    let ptr = {
+        let state_ref = state.borrow_mut();
+        let obj = JsObjStruct::new(proto, "global", Vec::new(), &mut *(state_ref.alloc_box.borrow_mut()));
+        Some(JsPtrEnum::JsObj(obj))
+    };
+    add_pervasive!(var, ptr, state, "this");
+}
+
 pub fn get_array_proto(len: f64, state: Rc<RefCell<ScopeManager>>) -> JsObjStruct {
     let (zero, undef) = scalar(JsType::JsNum(len));
     let array_length = NativeVar::new(zero, undef, "length", default_getter, array::array_length_setter);
@@ -68,6 +306,7 @@ pub fn get_array_proto(len: f64, state: Rc<RefCell<ScopeManager>>) -> JsObjStruc
     let to_string_ptr = JsPtrEnum::NativeFn(array_to_string);
 
 
+    let object_proto = get_native_proto("Object", state.clone()).map(Box::new);
     let mut state_ref = state.borrow_mut();
 
     // Not really sure what the `name` argument is for, but okay
@@ -79,7 +318,9 @@ pub fn get_array_proto(len: f64, state: Rc<RefCell<ScopeManager>>) -> JsObjStruc
 
     // No joke, the array prototype actually is an array...
     // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Array/prototype
-    array_proto.proto = Some(Box::new(array_proto.clone()));
+    let mut array_proto_proto = array_proto.clone();
+    array_proto_proto.proto = object_proto;
+    array_proto.proto = Some(Box::new(array_proto_proto));
 
     array_proto
 }
@@ -0,0 +1,47 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use var::{js_str_key, scalar_to_string};
+
+use jsrs_common::backend::Backend;
+use jsrs_common::js_error;
+use jsrs_common::types::coerce::AsString;
+use jsrs_common::types::js_str::JsStrStruct;
+use jsrs_common::types::js_var::{JsPtrEnum, JsPtrTag, JsType, JsVar};
+
+pub fn has_own_property(_state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                        args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let key = match args.first() {
+        Some(&(_, Some(ref ptr))) => ptr.as_string(),
+        Some(&(ref var, None)) => scalar_to_string(&var.t),
+        None => String::from("undefined"),
+    };
+
+    let b = match this {
+        Some((_, JsPtrEnum::JsObj(ref obj))) => obj.dict.contains_key(&js_str_key(&key)),
+        _ => false,
+    };
+
+    Ok((JsVar::new(JsType::JsBool(b)), None))
+}
+
+pub fn to_string(_state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                 _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let class = match this {
+        Some((_, JsPtrEnum::JsObj(_))) => "Object",
+        Some((_, JsPtrEnum::JsFn(_))) | Some((_, JsPtrEnum::NativeFn(_))) => "Function",
+        Some(_) => "Object",
+        None => "Undefined",
+    };
+
+    let s = format!("[object {}]", class);
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&s)))))
+}
+
+pub fn value_of(_state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    match this {
+        Some((var, ptr)) => Ok((var, Some(ptr))),
+        None => Ok((JsVar::new(JsType::JsUndef), None)),
+    }
+}
@@ -1,22 +1,27 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use number::{ptr_to_number, string_to_number};
+use var::{js_str_key, scalar_to_string};
+
 use jsrs_common::backend::Backend;
-use jsrs_common::js_error;
+use jsrs_common::js_error::{self, JsError};
 use jsrs_common::types::coerce::{AsBool, AsNumber, AsString};
 use jsrs_common::types::js_obj::JsObjStruct;
 use jsrs_common::types::js_str::JsStrStruct;
 use jsrs_common::types::js_var::{JsPtrEnum, JsPtrTag, JsType, JsVar};
 
 use super::array::array_to_string;
+use super::get_native_proto;
 
 pub fn object(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
               args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let proto = get_native_proto("Object", state.clone()).map(Box::new);
     let state_ref = state.borrow_mut();
     let alloc_box = state_ref.get_alloc_box();
     let (var, ptr) = args.first().map(|&(ref var, ref ptr)| (var.clone(), ptr.clone())).unwrap_or((
         JsVar::new(JsType::JsPtr(JsPtrTag::JsObj)),
-        Some(JsPtrEnum::JsObj(JsObjStruct::new(None, "Object", Vec::new(), &mut *(alloc_box.borrow_mut()))))
+        Some(JsPtrEnum::JsObj(JsObjStruct::new(proto, "Object", Vec::new(), &mut *(alloc_box.borrow_mut()))))
     ));
 
     Ok((var, ptr))
@@ -33,9 +38,9 @@ pub fn number(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
     let number = match args.first() {
         Some(&(ref var, Some(JsPtrEnum::JsObj(ref obj)))) if obj.proto.is_some() && obj.name == "array" => {
             let string = try!(array_to_string_helper(state.clone(), var.clone(), obj.clone()));
-            JsPtrEnum::JsStr(JsStrStruct::new(&string)).as_number()
+            string_to_number(&string)
         }
-        Some(&(_, Some(ref ptr))) => ptr.as_number(),
+        Some(&(_, Some(ref ptr))) => ptr_to_number(ptr),
         Some(&(ref var, None)) => var.as_number(),
         None => 0.0
     };
@@ -46,7 +51,7 @@ pub fn number(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
 pub fn array_to_string_helper(state: Rc<RefCell<Backend>>, var: JsVar, obj: JsObjStruct) -> js_error::Result<String> {
     let o_this = Some((var, JsPtrEnum::JsObj(obj)));
     let (o_var, o_ptr) = try!(array_to_string(state.clone(), o_this, Vec::new()));
-    Ok(o_ptr.map(|p| p.as_string()).unwrap_or(o_var.t.as_string()))
+    Ok(o_ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&o_var.t)))
 }
 
 pub fn string(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
@@ -55,10 +60,168 @@ pub fn string(state: Rc<RefCell<Backend>>, _this: Option<(JsVar, JsPtrEnum)>,
         Some(&(ref var, Some(JsPtrEnum::JsObj(ref obj)))) if obj.proto.is_some() && obj.name == "array" =>
             try!(array_to_string_helper(state.clone(), var.clone(), obj.clone())),
         Some(&(_, Some(ref ptr))) => ptr.as_string(),
-        Some(&(ref var, None)) => var.t.as_string(),
+        Some(&(ref var, None)) => scalar_to_string(&var.t),
         None => String::new()
     };
 
 
     Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&string)))))
 }
+
+/// Key under which `String`, `Number` and `Boolean` wrapper objects keep their primitive value.
+const PRIMITIVE_VALUE_KEY: &'static str = "%PrimitiveValue";
+
+/// ES5 ToObject: wraps a primitive in an object whose prototype is `String.prototype`,
+/// `Number.prototype` or `Boolean.prototype`. Values which already are pointers to objects or
+/// functions are returned unchanged.
+pub fn to_object(state: Rc<RefCell<Backend>>, var: JsVar, ptr: Option<JsPtrEnum>)
+                 -> js_error::Result<(JsVar, JsPtrEnum)> {
+    let (class, kv_tuples) = match ptr {
+        Some(JsPtrEnum::JsStr(s)) => {
+            let len = JsPtrEnum::JsStr(s.clone()).as_string().encode_utf16().count();
+            let value_var = JsVar::new(JsType::JsPtr(JsPtrTag::JsStr));
+            let length_var = JsVar::new(JsType::JsNum(len as f64));
+            ("String", vec![ (js_str_key(PRIMITIVE_VALUE_KEY), value_var, Some(JsPtrEnum::JsStr(s)))
+                           , (js_str_key("length"), length_var, None)
+                           ])
+        }
+        Some(ptr) => return Ok((var, ptr)),
+        None => {
+            let class = match var.t {
+                JsType::JsNum(_) => "Number",
+                JsType::JsBool(_) => "Boolean",
+                ref t => return Err(JsError::TypeError(format!("Cannot convert {} to object", t.as_string()))),
+            };
+            (class, vec![(js_str_key(PRIMITIVE_VALUE_KEY), JsVar::new(var.t.clone()), None)])
+        }
+    };
+
+    let proto = get_native_proto(class, state.clone()).map(Box::new);
+    let state_ref = state.borrow_mut();
+    let alloc_box = state_ref.get_alloc_box();
+    let obj = JsObjStruct::new(proto, class, kv_tuples, &mut *(alloc_box.borrow_mut()));
+
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsObj)), JsPtrEnum::JsObj(obj)))
+}
+
+/// Unwraps the primitive value of `this` for the `String.prototype`, `Number.prototype` and
+/// `Boolean.prototype` methods.
+fn this_primitive(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>) -> (JsVar, Option<JsPtrEnum>) {
+    match this {
+        Some((_, JsPtrEnum::JsObj(obj))) => match obj.dict.get(&js_str_key(PRIMITIVE_VALUE_KEY)) {
+            Some(var) => {
+                let state_ref = state.borrow_mut();
+                let alloc_box = state_ref.get_alloc_box();
+                let ptr = alloc_box.borrow_mut().find_id(&var.unique).map(|p| p.borrow().clone());
+                (var.clone(), ptr)
+            }
+            None => (JsVar::new(JsType::JsUndef), None),
+        },
+        Some((var, ptr)) => (var, Some(ptr)),
+        None => (JsVar::new(JsType::JsUndef), None),
+    }
+}
+
+pub fn string_to_string(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                        _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, ptr) = this_primitive(state, this);
+    let string = ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&var.t));
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&string)))))
+}
+
+pub fn string_char_at(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                      args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, ptr) = this_primitive(state, this);
+    let string = ptr.map(|p| p.as_string()).unwrap_or(scalar_to_string(&var.t));
+    let pos = args.first().map(|&(ref var, ref ptr)| ptr.as_ref().map(ptr_to_number).unwrap_or(var.as_number()));
+    let pos = pos.map(|n| if n.is_nan() { 0.0 } else { n.trunc() }).unwrap_or(0.0);
+
+    let units: Vec<u16> = string.encode_utf16().collect();
+    let c = if pos < 0.0 || pos >= units.len() as f64 {
+        String::new()
+    } else {
+        String::from_utf16_lossy(&units[pos as usize..pos as usize + 1])
+    };
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&c)))))
+}
+
+pub fn number_to_string(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                        _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, _) = this_primitive(state, this);
+    let string = scalar_to_string(&JsType::JsNum(var.as_number()));
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&string)))))
+}
+
+pub fn number_value_of(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                       _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, _) = this_primitive(state, this);
+    Ok((JsVar::new(JsType::JsNum(var.as_number())), None))
+}
+
+pub fn number_to_fixed(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                       args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, _) = this_primitive(state, this);
+    let digits = args.first().map(|&(ref var, ref ptr)| ptr.as_ref().map(ptr_to_number).unwrap_or(var.as_number()));
+    let digits = digits.map(|n| if n.is_nan() { 0.0 } else { n.trunc() }).unwrap_or(0.0);
+    if digits < 0.0 || digits > 20.0 {
+        return Err(JsError::RangeError(String::from("toFixed() digits argument must be between 0 and 20")));
+    }
+
+    let n = var.as_number();
+    let string = if n.is_nan() || n.abs() >= 1e21 {
+        scalar_to_string(&JsType::JsNum(n))
+    } else if n < 0.0 {
+        // Negative values which round to zero keep their sign, but `-0` formats as "0".
+        format!("-{}", fixed_digits(-n, digits as usize))
+    } else {
+        fixed_digits(n, digits as usize)
+    };
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&string)))))
+}
+
+/// Formats the non-negative `x` with `digits` digits after the decimal point. Of two equally
+/// close results, ES5 §15.7.4.5 picks the larger, where Rust's formatting would round to even, so
+/// the exact decimal expansion of `x` is rounded by hand.
+fn fixed_digits(x: f64, digits: usize) -> String {
+    // 1074 fractional digits are enough to write any double exactly.
+    let exact = format!("{:.1074}", x);
+    let point = exact.find('.').unwrap();
+    let mut kept: Vec<u8> = exact[..point + 1 + digits].bytes().filter(|&b| b != b'.').collect();
+
+    if exact.as_bytes()[point + 1 + digits] >= b'5' {
+        let mut i = kept.len();
+        loop {
+            if i == 0 {
+                kept.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if kept[i] == b'9' {
+                kept[i] = b'0';
+            } else {
+                kept[i] += 1;
+                break;
+            }
+        }
+    }
+
+    let int_len = kept.len() - digits;
+    let mut string = String::from_utf8(kept).unwrap();
+    if digits > 0 {
+        string.insert(int_len, '.');
+    }
+    string
+}
+
+pub fn boolean_to_string(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                         _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, _) = this_primitive(state, this);
+    let string = JsType::JsBool(var.as_bool()).as_string();
+    Ok((JsVar::new(JsType::JsPtr(JsPtrTag::JsStr)), Some(JsPtrEnum::JsStr(JsStrStruct::new(&string)))))
+}
+
+pub fn boolean_value_of(state: Rc<RefCell<Backend>>, this: Option<(JsVar, JsPtrEnum)>,
+                        _args: Vec<(JsVar, Option<JsPtrEnum>)>) -> js_error::Result<(JsVar, Option<JsPtrEnum>)> {
+    let (var, _) = this_primitive(state, this);
+    Ok((JsVar::new(JsType::JsBool(var.as_bool())), None))
+}
@@ -1,37 +1,32 @@
 use std::cell::RefCell;
+use std::f64::{INFINITY, NAN};
+use std::iter::repeat;
 use std::rc::Rc;
 
 use french_press::ScopeManager;
 use jsrs_common::backend::Backend;
 
-use jsrs_common::ast::*;
-use jsrs_common::ast::BinOp::*;
 use jsrs_common::types::coerce::{AsBool, AsNumber, AsString};
-use jsrs_common::types::js_var::{JsVar, JsType};
+use jsrs_common::types::js_var::{JsPtrEnum, JsVar};
 use jsrs_common::types::js_var::JsPtrEnum::*;
 use jsrs_common::types::js_var::JsType::*;
 use jsrs_common::types::js_var::JsPtrTag;
+use jsrs_common::types::js_obj::JsObjStruct;
+use jsrs_common::types::js_str::JsStrStruct;
 use jsrs_common::js_error::{self, JsError};
 
-use eval::eval_exp;
+use ast::*;
+use ast::BinOp::*;
+use eval::{eval_exp, to_primitive, Hint};
+use var::{find_property, js_str_key, scalar, scalar_to_string, JsVarValue};
+use native::fn_prototype_binding;
 
 macro_rules! b { ($e: expr) => { $e.as_bool() } }
 
 macro_rules! n { ($e: expr) => { $e.as_number() } }
-macro_rules! ni64 { ($e: expr) => { $e.as_number() as i64 } }
-macro_rules! nu64 { ($e: expr) => { $e.as_number() as u64 } }
-macro_rules! ni32 { ($e: expr) => { {
-        let n = $e.as_number();
-        if n.is_nan() {
-            0i32
-        } else {
-            n as i32
-        }
-    } } }
-macro_rules! nu32 { ($e: expr) => { $e.as_number() as u32 } }
 
 pub fn eval_binop(op: &BinOp, e1: &Exp, e2: &Exp,
-                  state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsType> {
+                  state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
     if let &And = op {
         let val1: JsVar = try!(eval_exp(e1, state.clone())).0;
         let b = if b!(val1) == false {
@@ -40,7 +35,7 @@ pub fn eval_binop(op: &BinOp, e1: &Exp, e2: &Exp,
             let val2: JsVar = try!(eval_exp(e2, state.clone())).0;
             JsBool(b!(val2))
         };
-        return Ok(b);
+        return Ok(scalar(b));
     } else if let &Or = op {
         let val1: JsVar = try!(eval_exp(e1, state.clone())).0;
         let b = if b!(val1) == true {
@@ -49,147 +44,103 @@ pub fn eval_binop(op: &BinOp, e1: &Exp, e2: &Exp,
             let val2: JsVar = try!(eval_exp(e2, state.clone())).0;
             JsBool(b!(val2))
         };
-        return Ok(b);
+        return Ok(scalar(b));
     }
 
-    let val1_is_instance_var = match e1 {
-        &Exp::InstanceVar(..) |
-        &Exp::KeyAccessor(..) => true,
-        _ => false
-    };
-
-    let val2_is_instance_var = match e2 {
-        &Exp::InstanceVar(..) |
-        &Exp::KeyAccessor(..) => true,
-        _ => false
-    };
+    let v1 = try!(eval_exp(e1, state.clone()));
+    let v2 = try!(eval_exp(e2, state.clone()));
+    eval_binop_values(op, v1, v2, state)
+}
 
-    let (val1, ptr1) = try!(eval_exp(e1, state.clone()));
-    let (val2, ptr2) = try!(eval_exp(e2, state.clone()));
+/// Apply a binary operator to two evaluated operands. `&&` and `||` short-circuit, so only
+/// `eval_binop` evaluates them.
+pub fn eval_binop_values(op: &BinOp, v1: JsVarValue, v2: JsVarValue,
+                         state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let (val1, ptr1) = v1;
+    let (val2, ptr2) = v2;
 
-    if let Err(e) = state.borrow_mut().alloc(val1.clone(), ptr1) {
+    if let Err(e) = state.borrow_mut().alloc(val1.clone(), ptr1.clone()) {
         return Err(JsError::from(e));
     }
 
-    if let Err(e) = state.borrow_mut().alloc(val2.clone(), ptr2) {
+    if let Err(e) = state.borrow_mut().alloc(val2.clone(), ptr2.clone()) {
         return Err(JsError::from(e));
     }
 
     let v = match *op {
-        And => {
-            println!("{:?}", val1);
-            if b!(val1) == false {
-                JsBool(false)
-            } else {
-                JsBool(b!(val2))
-            }
+        // Both operands have already been evaluated, so these can't short-circuit here.
+        And | Or =>
+            return Err(JsError::UnimplementedError(String::from("&& and || in eval_binop_values, number.rs"))),
+
+        Ge | Gt | Le | Lt => {
+            let p1 = try!(to_primitive((val1, ptr1), Hint::Number, state.clone()));
+            let p2 = try!(to_primitive((val2, ptr2), Hint::Number, state));
+            // An undefined comparison (one involving NaN) is false for every operator.
+            JsBool(match *op {
+                Lt => less_than(&p1, &p2) == Some(true),
+                Gt => less_than(&p2, &p1) == Some(true),
+                Le => less_than(&p2, &p1) == Some(false),
+                _  => less_than(&p1, &p2) == Some(false),
+            })
         }
-        Or  => JsBool(b!(val1) || b!(val2)),
 
-        Ge  => JsBool(b!(val1) >= b!(val2)),
-        Gt  => JsBool(b!(val1) >  b!(val2)),
-        Le  => JsBool(b!(val1) <= b!(val2)),
-        Lt  => JsBool(b!(val1) <  b!(val2)),
+        Neq => JsBool(!try!(abstract_equal((val1, ptr1), (val2, ptr2), state))),
+        Eql => JsBool(try!(abstract_equal((val1, ptr1), (val2, ptr2), state))),
 
-        Neq => {
-            if let Ok(JsBool(b)) = eval_binop(&Eql, e1, e2, state) {
-                JsBool(!b)
-            } else {
-                JsBool(false)
-            }
-        }
-        Eql => {
-            let b = match (&val1.t, &val2.t) {
-                (&JsNull,  &JsNull)  => false,
-                (&JsUndef, &JsNull)  => false,
-                (&JsNull,  &JsUndef) => false,
-                (&JsUndef, &JsUndef) => false,
-
-                (&JsNum(ref n1), &JsNum(ref n2)) => n1 == n2,
-                (&JsBool(ref b1), &JsBool(ref b2)) => b1 == b2,
-                (&JsPtr(_), &JsPtr(_)) => {
-                    let ptr1 = try_load!(state, &val1, val1_is_instance_var);
-                    let ptr2 = try_load!(state, &val2, val2_is_instance_var);
-                    match (&ptr1, &ptr2) {
-                        (&Some(JsSym(_)),      &Some(JsSym(_))) => val1 == val2,
-                        (&Some(JsStr(ref s1)), &Some(JsStr(ref s2))) => s1 == s2,
-                        (&Some(JsObj(_)),      &Some(JsObj(_))) => val1 == val2,
-                        (&Some(JsFn(_)),       &Some(JsFn(_))) => val1 == val2,
-                        _ => false,
-                    }
-                },
-
-                (&JsNum(ref n), &JsPtr(_)) =>
-                    try_load!(state, &val2, val2_is_instance_var).map_or(false, |ptr| *n == n!(ptr)),
-                (&JsPtr(_), &JsNum(ref n)) =>
-                    try_load!(state, &val2,val2_is_instance_var).map_or(false, |ptr| *n == n!(ptr)),
-
-                (&JsBool(_), &JsPtr(_)) =>
-                    try_load!(state, &val2, val2_is_instance_var).map_or(false, |ptr| n!(val1) == n!(ptr)),
-                (&JsPtr(_), &JsBool(_)) =>
-                    try_load!(state, &val2, val2_is_instance_var).map_or(false, |ptr| n!(val2) == n!(ptr)),
-                _ => false,
-            };
-            JsBool(b)
+        EqlStrict => JsBool(strict_equal(&(val1, ptr1), &(val2, ptr2))),
+        NeqStrict => JsBool(!strict_equal(&(val1, ptr1), &(val2, ptr2))),
+
+        BitOr | BitXor | BitAnd | ShiftLeft | ShiftRight | ShiftRightUnsigned => {
+            let n1 = try!(to_number((val1, ptr1), state.clone()));
+            let n2 = try!(to_number((val2, ptr2), state));
+            // Shift counts only use their low five bits, so they never overflow.
+            let shift = to_uint32(n2) & 0x1f;
+            JsNum(match *op {
+                BitOr      => (to_int32(n1) | to_int32(n2)) as f64,
+                BitXor     => (to_int32(n1) ^ to_int32(n2)) as f64,
+                BitAnd     => (to_int32(n1) & to_int32(n2)) as f64,
+                ShiftLeft  => (to_int32(n1) << shift) as f64,
+                ShiftRight => (to_int32(n1) >> shift) as f64,
+                _          => (to_uint32(n1) >> shift) as f64,
+            })
         }
 
-        EqlStrict => {
-            let b = match (&val1.t, &val2.t) {
-                (&JsNull,      &JsNull) => true,
-                (&JsUndef,     &JsUndef) => true,
-                (&JsNum(ref n1),   &JsNum(ref n2)) => n1 == n2,
-                (&JsBool(ref b1),  &JsBool(ref b2)) => b1 == b2,
-                (&JsPtr(_), &JsPtr(_)) => {
-                    let ptr1 = try_load!(state, &val1, val1_is_instance_var);
-                    let ptr2 = try_load!(state, &val2, val2_is_instance_var);
-                    match (&ptr1, &ptr2) {
-                        (&Some(JsSym(_)),      &Some(JsSym(_))) => val1 == val2,
-                        (&Some(JsStr(ref s1)), &Some(JsStr(ref s2))) => s1 == s2,
-                        (&Some(JsObj(_)),      &Some(JsObj(_))) => val1 == val2,
-                        (&Some(JsFn(_)),       &Some(JsFn(_))) => val1 == val2,
-                        _ => false,
-                    }
-                }
-                _ => false,
-            };
-            JsBool(b)
+        Plus  => return add((val1, ptr1), (val2, ptr2), state),
+        Minus | Slash | Star | Mod | Exponent => {
+            let n1 = try!(to_number((val1, ptr1), state.clone()));
+            let n2 = try!(to_number((val2, ptr2), state));
+            JsNum(match *op {
+                Minus => n1 - n2,
+                Slash => n1 / n2,
+                Star  => n1 * n2,
+                // Rust's `%` is C's fmod, which is exactly ES5 §11.5.3: the result takes the
+                // dividend's sign, and is NaN for an infinite dividend or a zero divisor.
+                Mod   => n1 % n2,
+                _     => exponentiate(n1, n2),
+            })
         }
-        NeqStrict => {
-            if let Ok(JsBool(b)) = eval_binop(&EqlStrict, e1, e2, state) {
-                JsBool(!b)
-            } else {
-                JsBool(false)
+        In => {
+            let key = ptr1.as_ref().map(|p| p.as_string()).unwrap_or(scalar_to_string(&val1.t));
+            match ptr2 {
+                Some(JsObj(ref obj)) => JsBool(find_property(obj, &js_str_key(&key), state.clone()).is_some()),
+                Some(JsFn(_)) => JsBool(key == "prototype"),
+                ref other => {
+                    let err_str = other.as_ref().map(|p| p.as_string()).unwrap_or(scalar_to_string(&val2.t));
+                    return Err(JsError::TypeError(format!("Cannot use 'in' operator to search for '{}' in {}",
+                                                          key, err_str)));
+                }
             }
         }
-
-        BitOr => JsNum((ni32!(val1) | ni32!(val2)) as f64),
-        BitXor => JsNum((ni32!(val1) ^ ni32!(val2)) as f64),
-        BitAnd => JsNum((ni32!(val1) & ni32!(val2)) as f64),
-
-        // TODO: Rust panics on shift overflow, and I don't want this.
-        ShiftLeft          => JsNum(0.0),
-        ShiftRight         => JsNum(0.0),
-        ShiftRightUnsigned => JsNum(0.0),
-        //ShiftLeft          => JsNum((ni32!(val1) << ni32!(val2)) as f64),
-        //ShiftRight         => JsNum((ni32!(val1) >> ni32!(val2)) as f64),
-        //ShiftRightUnsigned => JsNum((nu32!(val1) >> ni32!(val2)) as f64),
-
-        Minus => JsNum(n!(val1) - n!(val2)),
-        Plus  => JsNum(n!(val1) + n!(val2)),
-        Slash => JsNum(n!(val1) / n!(val2)),
-        Star  => JsNum(n!(val1) * n!(val2)),
-        Mod   => JsNum(n!(val1) % n!(val2)),
-        Exponent   => JsNum(n!(val1) % n!(val2)),
         InstanceOf => {
-            let ptr = try_load!(state, &val1, val1_is_instance_var);
-
-            let b = match (ptr, &val2.t) {
-                (Some(JsObj(ref obj)), &JsPtr(JsPtrTag::NativeFn { ref name})) => &obj.name == name,
+            let b = match (ptr1, &val2.t) {
+                (Some(JsObj(ref obj)), &JsPtr(JsPtrTag::NativeFn { ref name})) =>
+                    &obj.name == name || proto_chain_contains(obj, name),
+                (Some(JsObj(ref obj)), &JsPtr(JsPtrTag::JsFn{..})) =>
+                    proto_chain_contains(obj, &fn_prototype_binding(&val2).0),
                 (_, &JsPtr(JsPtrTag::NativeFn {..})) => false,
                 (_, &JsPtr(JsPtrTag::JsFn{..})) => false,
                 _ => {
-                    let ptr2 = try_load!(state, &val2, val2_is_instance_var);
-                    let err_str = ptr2.map(|p| p.as_string()).unwrap_or(val2.t.as_string());
+                    let err_str = ptr2.map(|p| p.as_string()).unwrap_or(scalar_to_string(&val2.t));
                     return Err(JsError::TypeError(format!("Expecting a function in instanceof check, but got {}", err_str)));
                 }
             };
@@ -197,5 +148,759 @@ pub fn eval_binop(op: &BinOp, e1: &Exp, e2: &Exp,
             JsBool(b)
         }
     };
-    Ok(v)
+    Ok(scalar(v))
+}
+
+/// The addition operator, `+` (ES5 §11.6.1). If either operand is a string once both have been
+/// converted to primitives, the result is their concatenation; otherwise it is their sum.
+fn add(v1: JsVarValue, v2: JsVarValue, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<JsVarValue> {
+    let p1 = try!(to_primitive(v1, Hint::Number, state.clone()));
+    let p2 = try!(to_primitive(v2, Hint::Number, state.clone()));
+
+    if type_of(&p1) != Type::String && type_of(&p2) != Type::String {
+        return Ok(scalar(JsNum(primitive_to_number(&p1) + primitive_to_number(&p2))));
+    }
+
+    let s = format!("{}{}", primitive_to_string(&p1), primitive_to_string(&p2));
+    let var = JsVar::new(JsPtr(JsPtrTag::JsStr));
+    let ptr = Some(JsStr(JsStrStruct::new(&s)));
+    try!(state.borrow_mut().alloc(var.clone(), ptr.clone()));
+    Ok((var, ptr))
+}
+
+/// The type of a value (ES5 §8), as far as comparisons are concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    Undefined,
+    Null,
+    Boolean,
+    Number,
+    String,
+    Object,
+}
+
+fn type_of(v: &JsVarValue) -> Type {
+    match (&v.0.t, &v.1) {
+        (&JsUndef, _) => Type::Undefined,
+        (&JsNull, _) => Type::Null,
+        (&JsBool(_), _) => Type::Boolean,
+        (&JsNum(_), _) => Type::Number,
+        (_, &Some(JsStr(_))) => Type::String,
+        _ => Type::Object,
+    }
+}
+
+/// ToNumber (ES5 §9.3) of a primitive value.
+fn primitive_to_number(v: &JsVarValue) -> f64 {
+    match v.1 {
+        Some(ref ptr) => ptr_to_number(ptr),
+        None => n!(v.0),
+    }
+}
+
+/// ToNumber (ES5 §9.3) of a heap value, without calling any methods on objects. Strings are
+/// parsed by `string_to_number`.
+pub fn ptr_to_number(ptr: &JsPtrEnum) -> f64 {
+    match *ptr {
+        JsStr(ref s) => string_to_number(&s.text),
+        ref ptr => n!(ptr),
+    }
+}
+
+/// Whether `c` is StrWhiteSpaceChar (ES5 §9.3.1): whitespace or a line terminator.
+fn is_str_whitespace(c: char) -> bool {
+    match c {
+        '\t' | '\u{b}' | '\u{c}' | ' ' | '\u{a0}' | '\u{feff}' |
+        '\n' | '\r' | '\u{2028}' | '\u{2029}' |
+        '\u{1680}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => true,
+        c => '\u{2000}' <= c && c <= '\u{200a}',
+    }
+}
+
+/// Whether `s` is a nonempty run of decimal digits.
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b'0' <= b && b <= b'9')
+}
+
+/// ToNumber (ES5 §9.3.1) of a string. Surrounding whitespace is ignored, and an empty string is
+/// 0. Otherwise the string has to be a hexadecimal integer (`0x1F`), `Infinity`, or a decimal
+/// number with an optional sign, fraction and exponent; anything else is NaN.
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_str_whitespace);
+    if s.is_empty() {
+        return 0.0;
+    }
+
+    if s.starts_with("0x") || s.starts_with("0X") {
+        let hex = &s[2..];
+        if hex.is_empty() || !hex.chars().all(|c| c.is_digit(16)) {
+            return NAN;
+        }
+        return hex.chars().fold(0.0, |n, c| n * 16.0 + c.to_digit(16).unwrap() as f64);
+    }
+
+    let (sign, unsigned) = if s.starts_with('-') {
+        (-1.0, &s[1..])
+    } else if s.starts_with('+') {
+        (1.0, &s[1..])
+    } else {
+        (1.0, s)
+    };
+
+    if unsigned == "Infinity" {
+        return sign * INFINITY;
+    }
+
+    // StrUnsignedDecimalLiteral: digits with an optional fraction, or a fraction alone, followed
+    // by an optional exponent. Rust accepts a superset of this ("inf", "NaN", ...), so the string
+    // is checked before being handed to it.
+    let (significand, exponent) = match unsigned.find(|c| c == 'e' || c == 'E') {
+        Some(i) => (&unsigned[..i], Some(&unsigned[i + 1..])),
+        None => (unsigned, None),
+    };
+    let (int, frac) = match significand.find('.') {
+        Some(i) => (&significand[..i], Some(&significand[i + 1..])),
+        None => (significand, None),
+    };
+
+    let significand_ok = match frac {
+        Some(frac) => (int.is_empty() || is_digits(int)) && (frac.is_empty() || is_digits(frac)) &&
+                      int.len() + frac.len() > 0,
+        None => is_digits(int),
+    };
+    let exponent_ok = match exponent {
+        Some(e) if e.starts_with('+') || e.starts_with('-') => is_digits(&e[1..]),
+        Some(e) => is_digits(e),
+        None => true,
+    };
+
+    if !significand_ok || !exponent_ok {
+        return NAN;
+    }
+
+    match unsigned.parse::<f64>() {
+        Ok(n) => sign * n,
+        Err(_) => NAN,
+    }
+}
+
+/// The exponentiation operator, `**` (ES2016 §12.7.3.4, which defers to `Math.pow`). IEEE 754
+/// `pow` already agrees with it on signed zeros and infinities; the exceptions are that a NaN
+/// exponent always gives NaN, and so does raising ±1 to an infinite power.
+fn exponentiate(base: f64, exponent: f64) -> f64 {
+    if exponent.is_nan() || (base.abs() == 1.0 && exponent.is_infinite()) {
+        NAN
+    } else {
+        base.powf(exponent)
+    }
+}
+
+/// ToNumber (ES5 §9.3) of any value, converting objects to primitives first.
+pub fn to_number(v: JsVarValue, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<f64> {
+    let p = try!(to_primitive(v, Hint::Number, state));
+    Ok(primitive_to_number(&p))
+}
+
+/// ToUint32 (ES5 §9.6): truncates `n` and wraps it modulo 2^32. NaN and the infinities become 0.
+pub fn to_uint32(n: f64) -> u32 {
+    const TWO_32: f64 = 4294967296.0;
+    if !n.is_finite() {
+        return 0;
+    }
+
+    // Both steps are exact, since every integer below 2^53 is representable.
+    let m = n.trunc() % TWO_32;
+    if m < 0.0 { (m + TWO_32) as u32 } else { m as u32 }
+}
+
+/// ToInt32 (ES5 §9.5): ToUint32, reinterpreted as a signed two's-complement integer.
+pub fn to_int32(n: f64) -> i32 {
+    to_uint32(n) as i32
+}
+
+/// ToUint16 (ES5 §9.7): truncates `n` and wraps it modulo 2^16.
+// Nothing needs this until `String.fromCharCode` exists.
+#[allow(dead_code)]
+pub fn to_uint16(n: f64) -> u16 {
+    to_uint32(n) as u16
+}
+
+/// ToString (ES5 §9.8) of a primitive value.
+fn primitive_to_string(v: &JsVarValue) -> String {
+    match v.1 {
+        Some(ref ptr) => ptr.as_string(),
+        None => scalar_to_string(&v.0.t),
+    }
+}
+
+/// ToString (ES5 §9.8.1) of a number: the shortest decimal that converts back to the same
+/// number, written out in full from 1e-6 up to (but excluding) 1e21, and in exponential
+/// notation outside that range.
+pub fn number_to_string(m: f64) -> String {
+    if m.is_nan() {
+        return String::from("NaN");
+    } else if m == 0.0 {
+        return String::from("0");
+    } else if m < 0.0 {
+        return format!("-{}", number_to_string(-m));
+    } else if m.is_infinite() {
+        return String::from("Infinity");
+    }
+
+    // `{:e}` prints the shortest round-tripping digits as "d.ddde<exponent>". In the spec's terms,
+    // `digits` is s, `k` is the number of digits, and m = s × 10^(n - k).
+    let formatted = format!("{:e}", m);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    let digits: String = mantissa.chars().filter(|&c| c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent[1..].parse::<i32>().unwrap() + 1;
+    let zeros = |count: i32| repeat('0').take(count as usize).collect::<String>();
+
+    if k <= n && n <= 21 {
+        digits + &zeros(n - k)
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        format!("{}.{}", int, frac)
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", zeros(-n), digits)
+    } else {
+        let sign = if n > 0 { "+" } else { "-" };
+        let (first, rest) = digits.split_at(1);
+        if rest.is_empty() {
+            format!("{}e{}{}", first, sign, (n - 1).abs())
+        } else {
+            format!("{}.{}e{}{}", first, rest, sign, (n - 1).abs())
+        }
+    }
+}
+
+/// Strict equality, `===` (ES5 §11.9.6). Strings compare by contents; objects and functions by
+/// identity.
+pub fn strict_equal(v1: &JsVarValue, v2: &JsVarValue) -> bool {
+    let t = type_of(v1);
+    if t != type_of(v2) {
+        return false;
+    }
+
+    match t {
+        Type::Undefined | Type::Null => true,
+        Type::Boolean | Type::Number => v1.0.t == v2.0.t,
+        Type::String => match (&v1.1, &v2.1) {
+            (&Some(JsStr(ref s1)), &Some(JsStr(ref s2))) => s1.text == s2.text,
+            _ => false,
+        },
+        Type::Object => v1.0.unique == v2.0.unique,
+    }
+}
+
+/// Abstract equality, `==` (ES5 §11.9.3).
+pub fn abstract_equal(v1: JsVarValue, v2: JsVarValue, state: Rc<RefCell<ScopeManager>>) -> js_error::Result<bool> {
+    let (t1, t2) = (type_of(&v1), type_of(&v2));
+    if t1 == t2 {
+        return Ok(strict_equal(&v1, &v2));
+    }
+
+    match (t1, t2) {
+        (Type::Undefined, Type::Null) | (Type::Null, Type::Undefined) => Ok(true),
+        (Type::Number, Type::String) | (Type::String, Type::Number) =>
+            Ok(primitive_to_number(&v1) == primitive_to_number(&v2)),
+        (Type::Boolean, _) => abstract_equal(scalar(JsNum(primitive_to_number(&v1))), v2, state),
+        (_, Type::Boolean) => abstract_equal(v1, scalar(JsNum(primitive_to_number(&v2))), state),
+        (Type::Number, Type::Object) | (Type::String, Type::Object) => {
+            let p2 = try!(to_primitive(v2, Hint::Number, state.clone()));
+            abstract_equal(v1, p2, state)
+        }
+        (Type::Object, Type::Number) | (Type::Object, Type::String) => {
+            let p1 = try!(to_primitive(v1, Hint::Number, state.clone()));
+            abstract_equal(p1, v2, state)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The abstract relational comparison `p1 < p2` of two primitives (ES5 §11.8.5). Strings compare
+/// by UTF-16 code units, anything else as numbers; `None` is the undefined result of comparing
+/// with NaN.
+fn less_than(p1: &JsVarValue, p2: &JsVarValue) -> Option<bool> {
+    match (&p1.1, &p2.1) {
+        (&Some(JsStr(ref s1)), &Some(JsStr(ref s2))) => Some(s1.text.encode_utf16().lt(s2.text.encode_utf16())),
+        _ => {
+            let (n1, n2) = (primitive_to_number(p1), primitive_to_number(p2));
+            if n1.is_nan() || n2.is_nan() {
+                None
+            } else {
+                Some(n1 < n2)
+            }
+        }
+    }
+}
+
+/// Walks the prototype chain of `obj` (not including `obj` itself), looking for a prototype
+/// with the given name.
+fn proto_chain_contains(obj: &JsObjStruct, name: &str) -> bool {
+    let mut proto = obj.proto.as_ref();
+    while let Some(p) = proto {
+        if p.name == name {
+            return true;
+        }
+        proto = p.proto.as_ref();
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use std::f64::{INFINITY, NAN, NEG_INFINITY};
+
+    use french_press::{init_gc, ScopeManager};
+    use jsrs_common::types::coerce::AsString;
+    use jsrs_common::types::js_var::JsType;
+
+    use ast::BinOp;
+    use eval::eval_string;
+    use native::add_pervasives;
+    use var::scalar;
+
+    use super::{eval_binop_values, number_to_string, string_to_number, to_int32, to_uint16, to_uint32};
+
+    /// Values of every type for the tables below to compare.
+    const SETUP: &'static str = "var o = {}; var p = {}; var n = new Number(5); var a = [2];\n";
+
+    /// (x, y, x == y, x === y)
+    const EQUALITY: &'static [(&'static str, &'static str, bool, bool)] = &[
+        ("undefined", "undefined", true,  true),
+        ("undefined", "null",      true,  false),
+        ("undefined", "false",     false, false),
+        ("undefined", "0",         false, false),
+        ("undefined", "\"\"",      false, false),
+        ("undefined", "o",         false, false),
+        ("null",      "null",      true,  true),
+        ("null",      "false",     false, false),
+        ("null",      "0",         false, false),
+        ("null",      "\"\"",      false, false),
+        ("null",      "o",         false, false),
+        ("true",      "true",      true,  true),
+        ("true",      "false",     false, false),
+        ("true",      "1",         true,  false),
+        ("false",     "0",         true,  false),
+        ("true",      "\"1\"",     true,  false),
+        ("false",     "\"\"",      true,  false),
+        ("true",      "o",         false, false),
+        ("true",      "[1]",       true,  false),
+        ("1",         "1",         true,  true),
+        ("0",         "-0",        true,  true),
+        ("0 / 0",     "0 / 0",     false, false),
+        ("1",         "\"1\"",     true,  false),
+        ("0",         "\"\"",      true,  false),
+        ("1",         "\"a\"",     false, false),
+        ("5",         "n",         true,  false),
+        ("2",         "a",         true,  false),
+        ("\"a\"",     "\"a\"",     true,  true),
+        ("\"a\"",     "\"b\"",     false, false),
+        ("\"5\"",     "n",         true,  false),
+        ("\"[object Object]\"", "o", true, false),
+        ("o",         "o",         true,  true),
+        ("o",         "p",         false, false),
+        ("n",         "new Number(5)", false, false),
+    ];
+
+    /// (x, y, x < y, x <= y)
+    const RELATIONAL: &'static [(&'static str, &'static str, bool, bool)] = &[
+        ("1",         "2",         true,  true),
+        ("3",         "2",         false, false),
+        ("2",         "2",         false, true),
+        ("-1",        "0",         true,  true),
+        ("0 / 0",     "1",         false, false),
+        ("1",         "0 / 0",     false, false),
+        ("\"a\"",     "\"b\"",     true,  true),
+        ("\"b\"",     "\"a\"",     false, false),
+        ("\"a\"",     "\"ab\"",    true,  true),
+        ("\"B\"",     "\"a\"",     true,  true),
+        ("\"10\"",    "\"9\"",     true,  true),
+        ("\"10\"",    "9",         false, false),
+        ("\"\"",      "1",         true,  true),
+        ("null",      "1",         true,  true),
+        ("null",      "0",         false, true),
+        ("undefined", "1",         false, false),
+        ("undefined", "undefined", false, false),
+        ("null",      "undefined", false, false),
+        ("false",     "true",      true,  true),
+        ("true",      "2",         true,  true),
+        ("n",         "6",         true,  true),
+        ("a",         "10",        true,  true),
+        ("a",         "\"10\"",    false, false),
+        ("o",         "p",         false, true),
+    ];
+
+    /// (n, ToInt32(n), ToUint32(n), ToUint16(n))
+    const INTEGER_CONVERSIONS: &'static [(f64, i32, u32, u16)] = &[
+        (0.0,             0,           0,          0),
+        (-0.0,            0,           0,          0),
+        (1.9,             1,           1,          1),
+        (-1.9,            -1,          4294967295, 65535),
+        (65535.0,         65535,       65535,      65535),
+        (65536.0,         65536,       65536,      0),
+        (65537.9,         65537,       65537,      1),
+        (-65536.0,        -65536,      4294901760, 0),
+        (2147483647.0,    2147483647,  2147483647, 65535),
+        (2147483648.0,    -2147483648, 2147483648, 0),
+        (-2147483648.0,   -2147483648, 2147483648, 0),
+        (-2147483649.0,   2147483647,  2147483647, 65535),
+        (4294967295.0,    -1,          4294967295, 65535),
+        (4294967296.0,    0,           0,          0),
+        (4294967297.0,    1,           1,          1),
+        (-4294967296.0,   0,           0,          0),
+        (-4294967297.0,   -1,          4294967295, 65535),
+        (1e20,            1661992960,  1661992960, 0),
+        (9007199254740993.0, 0,        0,          0),
+    ];
+
+    /// (x, y, x % y), per ES5 §11.5.3.
+    const MODULO: &'static [(f64, f64, f64)] = &[
+        (5.5,          2.0,          1.5),
+        (-5.5,         2.0,          -1.5),
+        (5.5,          -2.0,         1.5),
+        (-5.5,         -2.0,         -1.5),
+        (-4.0,         2.0,          -0.0),
+        (4.0,          -2.0,         0.0),
+        (NAN,          1.0,          NAN),
+        (1.0,          NAN,          NAN),
+        (INFINITY,     1.0,          NAN),
+        (NEG_INFINITY, INFINITY,     NAN),
+        (1.0,          0.0,          NAN),
+        (1.0,          -0.0,         NAN),
+        (0.0,          0.0,          NAN),
+        (1.0,          INFINITY,     1.0),
+        (-1.0,         NEG_INFINITY, -1.0),
+        (0.0,          1.0,          0.0),
+        (-0.0,         1.0,          -0.0),
+        (-0.0,         INFINITY,     -0.0),
+        (1e308,        3.0,          2.0),
+    ];
+
+    /// (x, y, x ** y), per ES2016 §12.7.3.4.
+    const EXPONENTIATION: &'static [(f64, f64, f64)] = &[
+        (2.0,          10.0,         1024.0),
+        (2.0,          -1.0,         0.5),
+        (4.0,          0.5,          2.0),
+        (-8.0,         3.0,          -512.0),
+        (-8.0,         1.0 / 3.0,    NAN),
+        (NAN,          0.0,          1.0),
+        (NAN,          -0.0,         1.0),
+        (NAN,          1.0,          NAN),
+        (1.0,          NAN,          NAN),
+        (1.0,          INFINITY,     NAN),
+        (-1.0,         NEG_INFINITY, NAN),
+        (0.5,          INFINITY,     0.0),
+        (0.5,          NEG_INFINITY, INFINITY),
+        (2.0,          INFINITY,     INFINITY),
+        (-2.0,         NEG_INFINITY, 0.0),
+        (INFINITY,     1.0,          INFINITY),
+        (INFINITY,     -1.0,         0.0),
+        (NEG_INFINITY, 3.0,          NEG_INFINITY),
+        (NEG_INFINITY, 2.0,          INFINITY),
+        (NEG_INFINITY, -3.0,         -0.0),
+        (NEG_INFINITY, -2.0,         0.0),
+        (0.0,          3.0,          0.0),
+        (0.0,          -3.0,         INFINITY),
+        (-0.0,         3.0,          -0.0),
+        (-0.0,         2.0,          0.0),
+        (-0.0,         -3.0,         NEG_INFINITY),
+        (-0.0,         -2.0,         INFINITY),
+    ];
+
+    /// (n, ToString(n)), per ES5 §9.8.1.
+    const NUMBER_TO_STRING: &'static [(f64, &'static str)] = &[
+        (0.0,                     "0"),
+        (-0.0,                    "0"),
+        (NAN,                     "NaN"),
+        (INFINITY,                "Infinity"),
+        (NEG_INFINITY,            "-Infinity"),
+        (1.0,                     "1"),
+        (-1.0,                    "-1"),
+        (100.0,                   "100"),
+        (1.5,                     "1.5"),
+        (-1.5,                    "-1.5"),
+        (0.1,                     "0.1"),
+        (123.456,                 "123.456"),
+        (1.0 / 3.0,               "0.3333333333333333"),
+        (4294967295.0,            "4294967295"),
+        (9007199254740993.0,      "9007199254740992"),
+        (1e20,                    "100000000000000000000"),
+        (123456789012345680000.0, "123456789012345680000"),
+        (1e21,                    "1e+21"),
+        (-1e21,                   "-1e+21"),
+        (1.2345e21,               "1.2345e+21"),
+        (1e100,                   "1e+100"),
+        (1.7976931348623157e308,  "1.7976931348623157e+308"),
+        (0.000001,                "0.000001"),
+        (0.0000015,               "0.0000015"),
+        (0.00001234,              "0.00001234"),
+        (1e-7,                    "1e-7"),
+        (-1e-7,                   "-1e-7"),
+        (1.5e-7,                  "1.5e-7"),
+        (5e-324,                  "5e-324"),
+    ];
+
+    /// (s, ToNumber(s)), per ES5 §9.3.1.
+    const STRING_TO_NUMBER: &'static [(&'static str, f64)] = &[
+        ("",                       0.0),
+        ("\t\n\r\u{a0}\u{feff}",   0.0),
+        ("  12  ",                 12.0),
+        ("\u{2003}7\u{2029}",      7.0),
+        ("007",                    7.0),
+        ("-12",                    -12.0),
+        ("+12",                    12.0),
+        ("-0",                     -0.0),
+        ("1.5",                    1.5),
+        (".5",                     0.5),
+        ("5.",                     5.0),
+        ("-.5",                    -0.5),
+        ("1e3",                    1000.0),
+        ("1E3",                    1000.0),
+        ("1e+3",                   1000.0),
+        ("1e-3",                   0.001),
+        ("1.5e2",                  150.0),
+        (".5e1",                   5.0),
+        ("1e400",                  INFINITY),
+        ("0x1F",                   31.0),
+        ("0X1f",                   31.0),
+        ("0xFFFFFFFF",             4294967295.0),
+        ("Infinity",               INFINITY),
+        ("+Infinity",              INFINITY),
+        ("-Infinity",              NEG_INFINITY),
+        ("0x",                     NAN),
+        ("0x1G",                   NAN),
+        ("-0x1F",                  NAN),
+        ("+0x1F",                  NAN),
+        (".",                      NAN),
+        ("-",                      NAN),
+        ("+-5",                    NAN),
+        ("1e",                     NAN),
+        ("1e+",                    NAN),
+        ("e3",                     NAN),
+        ("1 2",                    NAN),
+        ("12abc",                  NAN),
+        ("1_000",                  NAN),
+        ("infinity",               NAN),
+        ("inf",                    NAN),
+        ("NaN",                    NAN),
+        ("\u{85}1",                NAN),
+    ];
+
+    /// Whether `a` and `b` are the same number, telling 0 from -0 and treating NaN as itself.
+    fn same_number(a: f64, b: f64) -> bool {
+        (a.is_nan() && b.is_nan()) || (a == b && a.is_sign_negative() == b.is_sign_negative())
+    }
+
+    fn eval_number(op: &BinOp, x: f64, y: f64, state: Rc<RefCell<ScopeManager>>) -> f64 {
+        match eval_binop_values(op, scalar(JsType::JsNum(x)), scalar(JsType::JsNum(y)), state).unwrap().0.t {
+            JsType::JsNum(n) => n,
+            t => panic!("{} {:?} {} evaluated to {:?}", x, op, y, t),
+        }
+    }
+
+    fn eval_bool(source: &str, state: Rc<RefCell<ScopeManager>>) -> bool {
+        match eval_string(&format!("{};\n", source), state).unwrap().0.t {
+            JsType::JsBool(b) => b,
+            t => panic!("{} evaluated to {:?}", source, t),
+        }
+    }
+
+    #[test]
+    fn test_equality() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string(SETUP, state.clone()).unwrap();
+
+        for &(x, y, eql, strict) in EQUALITY {
+            // Both operators are symmetric.
+            for &(x, y) in &[(x, y), (y, x)] {
+                assert_eq!(eql, eval_bool(&format!("{} == {}", x, y), state.clone()), "{} == {}", x, y);
+                assert_eq!(!eql, eval_bool(&format!("{} != {}", x, y), state.clone()), "{} != {}", x, y);
+                assert_eq!(strict, eval_bool(&format!("{} === {}", x, y), state.clone()), "{} === {}", x, y);
+                assert_eq!(!strict, eval_bool(&format!("{} !== {}", x, y), state.clone()), "{} !== {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_relational() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+        eval_string(SETUP, state.clone()).unwrap();
+
+        for &(x, y, lt, le) in RELATIONAL {
+            assert_eq!(lt, eval_bool(&format!("{} < {}", x, y), state.clone()), "{} < {}", x, y);
+            assert_eq!(le, eval_bool(&format!("{} <= {}", x, y), state.clone()), "{} <= {}", x, y);
+            assert_eq!(lt, eval_bool(&format!("{} > {}", y, x), state.clone()), "{} > {}", y, x);
+            assert_eq!(le, eval_bool(&format!("{} >= {}", y, x), state.clone()), "{} >= {}", y, x);
+        }
+    }
+
+    #[test]
+    fn test_integer_conversions() {
+        for &(n, int32, uint32, uint16) in INTEGER_CONVERSIONS {
+            assert_eq!(int32, to_int32(n), "ToInt32({})", n);
+            assert_eq!(uint32, to_uint32(n), "ToUint32({})", n);
+            assert_eq!(uint16, to_uint16(n), "ToUint16({})", n);
+        }
+
+        for &n in &[::std::f64::NAN, ::std::f64::INFINITY, ::std::f64::NEG_INFINITY] {
+            assert_eq!(0, to_int32(n));
+            assert_eq!(0, to_uint32(n));
+            assert_eq!(0, to_uint16(n));
+        }
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+
+        let cases: &[(&str, f64)] = &[
+            ("1 << 31", -2147483648.0),
+            ("1 << 32", 1.0),
+            ("1 << 33", 2.0),
+            ("1 << -1", -2147483648.0),
+            ("1 << \"2\"", 4.0),
+            ("-1 >> 31", -1.0),
+            ("-8 >> 1", -4.0),
+            ("8 >> -1", 0.0),
+            ("-1 >>> 0", 4294967295.0),
+            ("-1 >>> 31", 1.0),
+            ("-8 >>> 1", 2147483644.0),
+            ("4294967296 >>> 0", 0.0),
+            ("4294967296 | 0", 0.0),
+            ("2147483648 | 0", -2147483648.0),
+            ("4294967295 & 255", 255.0),
+            ("-1 ^ 4294967295", 0.0),
+            ("null | 1", 1.0),
+            ("\"12\" & 4", 4.0),
+            ("(0 / 0) | 0", 0.0),
+            ("~4294967295", 0.0),
+            ("~\"1\"", -2.0),
+        ];
+
+        for &(source, expected) in cases {
+            match eval_string(&format!("{};\n", source), state.clone()).unwrap().0.t {
+                JsType::JsNum(n) => assert_eq!(expected, n, "{}", source),
+                t => panic!("{} evaluated to {:?}", source, t),
+            }
+        }
+    }
+
+    #[test]
+    fn test_modulo() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        for &(x, y, expected) in MODULO {
+            let n = eval_number(&BinOp::Mod, x, y, state.clone());
+            assert!(same_number(expected, n), "{} % {} = {}, expected {}", x, y, n, expected);
+        }
+
+        // For finite operands with a nonzero divisor, the result r has the sign of the dividend x,
+        // is smaller than the divisor y in magnitude, and x - r is an integral multiple of y.
+        let values = [-7.5, -3.0, -1.0, -0.25, -0.0, 0.0, 0.25, 1.0, 3.0, 7.5, 1e10];
+        for &x in values.iter() {
+            for &y in values.iter().filter(|&&y| y != 0.0) {
+                let r = eval_number(&BinOp::Mod, x, y, state.clone());
+                assert_eq!(x.is_sign_negative(), r.is_sign_negative(), "{} % {}", x, y);
+                assert!(r.abs() < y.abs(), "{} % {}", x, y);
+                assert_eq!(0.0, ((x - r) / y).fract(), "{} % {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_exponentiation() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        for &(x, y, expected) in EXPONENTIATION {
+            let n = eval_number(&BinOp::Exponent, x, y, state.clone());
+            assert!(same_number(expected, n), "{} ** {} = {}, expected {}", x, y, n, expected);
+        }
+    }
+
+    #[test]
+    fn test_number_to_string() {
+        for &(n, expected) in NUMBER_TO_STRING {
+            assert_eq!(expected, number_to_string(n), "ToString({})", n);
+        }
+
+        // 0.1 + 0.2 isn't 0.3, so it needs all seventeen digits to round-trip.
+        assert_eq!("0.30000000000000004", number_to_string(0.1 + 0.2));
+    }
+
+    #[test]
+    fn test_number_concatenation() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+
+        let cases: &[(&str, &str)] = &[
+            ("\"\" + 1", "1"),
+            ("\"\" + -0", "0"),
+            ("\"\" + 0.5", "0.5"),
+            ("\"\" + 1e21", "1e+21"),
+            ("\"\" + 1e-7", "1e-7"),
+            ("\"\" + 0 / 0", "NaN"),
+            ("\"x\" + 1 / 0", "xInfinity"),
+            ("1 + 2 + \"3\"", "33"),
+            ("\"1\" + 2 + 3", "123"),
+            ("[1.5, 1e21] + \"\"", "1.5,1e+21"),
+            ("String(1e-7)", "1e-7"),
+        ];
+
+        for &(source, expected) in cases {
+            let (var, ptr) = eval_string(&format!("{};\n", source), state.clone()).unwrap();
+            match ptr {
+                Some(ptr) => assert_eq!(expected, ptr.as_string(), "{}", source),
+                None => panic!("{} evaluated to {:?}", source, var.t),
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        for &(string, expected) in STRING_TO_NUMBER {
+            let n = string_to_number(string);
+            assert!(same_number(expected, n), "ToNumber({:?}) = {}, expected {}", string, n, expected);
+        }
+    }
+
+    #[test]
+    fn test_string_coercion() {
+        let state = Rc::new(RefCell::new(init_gc()));
+        add_pervasives(state.clone());
+
+        let cases: &[(&str, f64)] = &[
+            ("Number(\"0x1F\")", 31.0),
+            ("Number(\"  12  \")", 12.0),
+            ("Number(\"1e3\")", 1000.0),
+            ("Number(\"\")", 0.0),
+            ("Number(\"Infinity\")", INFINITY),
+            ("+\"0x10\"", 16.0),
+            ("-\" 5 \"", -5.0),
+            ("\"10\" - \"4\"", 6.0),
+            ("\"0x10\" * \"2\"", 32.0),
+            ("\"1e3\" / 10", 100.0),
+            ("\"0xF\" | 0", 15.0),
+            ("var s = \"0x1F\"; s++; s", 32.0),
+            ("Number(\"12px\")", NAN),
+            ("-\"abc\"", NAN),
+        ];
+
+        for &(source, expected) in cases {
+            match eval_string(&format!("{};\n", source), state.clone()).unwrap().0.t {
+                JsType::JsNum(n) => assert!(same_number(expected, n), "{} = {}, expected {}", source, n, expected),
+                t => panic!("{} evaluated to {:?}", source, t),
+            }
+        }
+
+        assert!(eval_bool("\" 0x10 \" == 16", state.clone()));
+        assert!(eval_bool("\"1e3\" < 2000", state.clone()));
+        assert!(eval_bool("isNaN(\"1e\")", state.clone()));
+        assert!(!eval_bool("isNaN(\" \")", state.clone()));
+    }
 }
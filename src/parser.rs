@@ -0,0 +1,883 @@
+//! A recursive descent parser for ECMAScript 5 programs (ES5 §11-§14), working on the tokens
+//! produced by `lexer`.
+
+use std::mem;
+use std::rc::Rc;
+
+use jsrs_common::js_error::{self, JsError};
+
+use ast::{BinOp, Exp, Stmt};
+use lexer::{self, Token, TokenKind};
+use number::{number_to_string, string_to_number};
+
+use unescape::unescape;
+
+/// Parse a script into its top-level statements.
+pub fn parse(source: &str) -> js_error::Result<Vec<Stmt>> {
+    let mut parser = Parser {
+        tokens: try!(lexer::tokenize(source)),
+        pos: 0,
+        labels: Vec::new(),
+        in_loop: false,
+        in_switch: false,
+    };
+    let mut body = Vec::new();
+    while parser.peek().is_some() {
+        body.push(try!(parser.parse_statement()));
+    }
+    Ok(body)
+}
+
+/// Turn a list of statements into a single statement.
+fn block_statement(mut block: Vec<Stmt>) -> Stmt {
+    let mut stmt = match block.pop() {
+        Some(stmt) => stmt,
+        None => return Stmt::Empty,
+    };
+    while let Some(prev) = block.pop() {
+        stmt = Stmt::Seq(Box::new(prev), Box::new(stmt));
+    }
+    stmt
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The labels enclosing the current statement, along with whether each labels a loop. These,
+    /// `in_loop` and `in_switch` tell which jumps are allowed (ES5 §12.7, §12.8, §12.12), and are
+    /// reset for each function body.
+    labels: Vec<(String, bool)>,
+    in_loop: bool,
+    in_switch: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.peek().map_or(false, |t| t.is(text))
+    }
+
+    /// Skip the next token if it is the keyword or punctuator `text`.
+    fn eat(&mut self, text: &str) -> bool {
+        if self.peek_is(text) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> js_error::Result<()> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_ident(&mut self) -> js_error::Result<String> {
+        match self.peek() {
+            Some(t) if t.kind == TokenKind::Ident => (),
+            _ => return Err(self.unexpected()),
+        }
+        self.pos += 1;
+        Ok(self.tokens[self.pos - 1].text.clone())
+    }
+
+    /// `message` with the position of the next token, or of the end of the input.
+    fn located(&self, message: &str) -> String {
+        let (line, column) = match self.peek() {
+            Some(t) => (t.line, t.column),
+            None => self.tokens.last().map_or((1, 1), |t| (t.line, t.column + t.text.chars().count())),
+        };
+        format!("{} (line {}, column {})", message, line, column)
+    }
+
+    /// A parse error at the next token, or at the end of the input.
+    fn error(&self, message: &str) -> JsError {
+        JsError::ParseError(self.located(message))
+    }
+
+    /// An early error (ES5 §16) at the next token, for a program which parses but isn't valid.
+    fn syntax_error(&self, message: &str) -> JsError {
+        JsError::SyntaxError(self.located(message))
+    }
+
+    fn unexpected(&self) -> JsError {
+        match self.peek() {
+            Some(t) => self.error(&format!("Unexpected token {}", t.text)),
+            None => self.error("Unexpected end of input"),
+        }
+    }
+
+    /// Whether the current statement may end here (ES5 §7.9): at a `;`, before a `}` or the end
+    /// of the input, or before a line terminator.
+    fn at_statement_end(&self) -> bool {
+        match self.peek() {
+            None => true,
+            Some(t) => t.newline_before || t.is(";") || t.is("}"),
+        }
+    }
+
+    fn consume_semicolon(&mut self) -> js_error::Result<()> {
+        if self.eat(";") || self.at_statement_end() {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn parse_statement(&mut self) -> js_error::Result<Stmt> {
+        let token = match self.peek() {
+            Some(t) => t.clone(),
+            None => return Err(self.unexpected()),
+        };
+        let keyword = match token.kind {
+            TokenKind::Keyword | TokenKind::Punct => &*token.text,
+            _ => "",
+        };
+
+        match keyword {
+            "{" => Ok(block_statement(try!(self.parse_block()))),
+            ";" => {
+                self.pos += 1;
+                Ok(Stmt::Empty)
+            }
+            "var" => {
+                self.pos += 1;
+                let decls = try!(self.parse_var_declarations(false));
+                try!(self.consume_semicolon());
+                Ok(decls)
+            }
+            "if" => {
+                self.pos += 1;
+                let condition = try!(self.parse_condition());
+                let if_block = try!(self.parse_substatement());
+                let else_block = if self.eat("else") { try!(self.parse_substatement()) } else { Vec::new() };
+                Ok(Stmt::If(condition, if_block, else_block))
+            }
+            "do" => {
+                self.pos += 1;
+                let block = try!(self.parse_loop_body());
+                try!(self.expect("while"));
+                let condition = try!(self.parse_condition());
+                // The semicolon after a do-while is always optional.
+                self.eat(";");
+                Ok(Stmt::DoWhile(block, condition))
+            }
+            "while" => {
+                self.pos += 1;
+                let condition = try!(self.parse_condition());
+                Ok(Stmt::While(condition, try!(self.parse_loop_body())))
+            }
+            "for" => self.parse_for(),
+            "switch" => self.parse_switch(),
+            "break" | "continue" => self.parse_jump(keyword == "break"),
+            "return" => {
+                self.pos += 1;
+                let exp = if self.at_statement_end() { Exp::Undefined } else { try!(self.parse_expression(false)) };
+                try!(self.consume_semicolon());
+                Ok(Stmt::Ret(exp))
+            }
+            "throw" => {
+                self.pos += 1;
+                if self.peek().map_or(true, |t| t.newline_before) {
+                    return Err(self.error("Illegal newline after throw"));
+                }
+                let exp = try!(self.parse_expression(false));
+                try!(self.consume_semicolon());
+                Ok(Stmt::Throw(exp))
+            }
+            "try" => self.parse_try(),
+            "function" => Ok(Stmt::FnDecl(try!(self.parse_function(true)))),
+            _ if token.kind == TokenKind::Ident && self.tokens.get(self.pos + 1).map_or(false, |t| t.is(":")) =>
+                self.parse_labelled(token.text),
+            _ => {
+                let exp = try!(self.parse_expression(false));
+                try!(self.consume_semicolon());
+                Ok(Stmt::BareExp(exp))
+            }
+        }
+    }
+
+    /// Parse `{ stmt... }`.
+    fn parse_block(&mut self) -> js_error::Result<Vec<Stmt>> {
+        try!(self.expect("{"));
+        let mut block = Vec::new();
+        while !self.eat("}") {
+            block.push(try!(self.parse_statement()));
+        }
+        Ok(block)
+    }
+
+    /// Parse the statement an `if` or a loop applies to, as a block.
+    fn parse_substatement(&mut self) -> js_error::Result<Vec<Stmt>> {
+        if self.peek_is("{") {
+            self.parse_block()
+        } else {
+            Ok(vec![try!(self.parse_statement())])
+        }
+    }
+
+    /// Parse the body of a loop, in which `break` and `continue` are allowed.
+    fn parse_loop_body(&mut self) -> js_error::Result<Vec<Stmt>> {
+        let in_loop = self.in_loop;
+        self.in_loop = true;
+        let block = self.parse_substatement();
+        self.in_loop = in_loop;
+        block
+    }
+
+    /// Parse `break` or `continue`, with an optional label on the same line.
+    fn parse_jump(&mut self, is_break: bool) -> js_error::Result<Stmt> {
+        let label = match self.tokens.get(self.pos + 1) {
+            Some(t) if t.kind == TokenKind::Ident && !t.newline_before => Some(t.text.clone()),
+            _ => None,
+        };
+
+        // `continue` can only name a loop's label.
+        match label {
+            Some(ref label) => if !self.labels.iter().any(|&(ref l, is_loop)| l == label && (is_break || is_loop)) {
+                return Err(self.syntax_error(&format!("Undefined label '{}'", label)));
+            },
+            None if is_break && !self.in_loop && !self.in_switch =>
+                return Err(self.syntax_error("Illegal break statement")),
+            None if !is_break && !self.in_loop => return Err(self.syntax_error("Illegal continue statement")),
+            None => (),
+        }
+
+        self.pos += if label.is_some() { 2 } else { 1 };
+        try!(self.consume_semicolon());
+        Ok(if is_break { Stmt::Break(label) } else { Stmt::Continue(label) })
+    }
+
+    /// Parse `label: stmt`.
+    fn parse_labelled(&mut self, label: String) -> js_error::Result<Stmt> {
+        if self.labels.iter().any(|&(ref l, _)| *l == label) {
+            return Err(self.syntax_error(&format!("Label '{}' has already been declared", label)));
+        }
+        self.pos += 2;
+
+        // In `a: b: while (...)`, both labels name the loop.
+        let mut next = self.pos;
+        while self.tokens.get(next).map_or(false, |t| t.kind == TokenKind::Ident) &&
+                self.tokens.get(next + 1).map_or(false, |t| t.is(":")) {
+            next += 2;
+        }
+        let is_loop = self.tokens.get(next).map_or(false, |t| t.is("do") || t.is("for") || t.is("while"));
+
+        self.labels.push((label.clone(), is_loop));
+        let stmt = self.parse_statement();
+        self.labels.pop();
+        Ok(Stmt::Labelled(label, Box::new(try!(stmt))))
+    }
+
+    /// Parse the parenthesized condition of an `if` or a `while`.
+    fn parse_condition(&mut self) -> js_error::Result<Exp> {
+        try!(self.expect("("));
+        let condition = try!(self.parse_expression(false));
+        try!(self.expect(")"));
+        Ok(condition)
+    }
+
+    /// Parse the declarations after `var`. Inside a `for` header, `no_in` keeps `in` from being
+    /// read as an operator.
+    fn parse_var_declarations(&mut self, no_in: bool) -> js_error::Result<Stmt> {
+        let mut decls = Vec::new();
+        loop {
+            let name = try!(self.expect_ident());
+            if self.eat("=") {
+                decls.push(Stmt::Decl(name, try!(self.parse_assignment(no_in))));
+            } else {
+                decls.push(Stmt::VarDecl(name));
+            }
+            if !self.eat(",") {
+                return Ok(block_statement(decls));
+            }
+        }
+    }
+
+    /// The operator of the compound assignment at the next token, such as `+` for `+=`.
+    fn compound_assign_op(&self) -> Option<BinOp> {
+        let t = match self.peek() {
+            Some(t) if t.kind == TokenKind::Punct => t,
+            _ => return None,
+        };
+        let op = match &*t.text {
+            "+=" => BinOp::Plus,
+            "-=" => BinOp::Minus,
+            "*=" => BinOp::Star,
+            "/=" => BinOp::Slash,
+            "%=" => BinOp::Mod,
+            "**=" => BinOp::Exponent,
+            "<<=" => BinOp::ShiftLeft,
+            ">>=" => BinOp::ShiftRight,
+            ">>>=" => BinOp::ShiftRightUnsigned,
+            "&=" => BinOp::BitAnd,
+            "|=" => BinOp::BitOr,
+            "^=" => BinOp::BitXor,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn parse_for(&mut self) -> js_error::Result<Stmt> {
+        self.pos += 1;
+        try!(self.expect("("));
+
+        let init = if self.peek_is(";") {
+            Stmt::Empty
+        } else if self.eat("var") {
+            let start = self.pos;
+            let name = try!(self.expect_ident());
+            if self.eat("in") {
+                return self.parse_for_in(Stmt::VarDecl(name));
+            }
+            self.pos = start;
+            try!(self.parse_var_declarations(true))
+        } else {
+            let init = try!(self.parse_expression(true));
+            match init {
+                // `for (a = b in c)` isn't a `for`-`in` loop.
+                Exp::Assign(..) | Exp::CompoundAssign(..) => (),
+                _ => if self.eat("in") {
+                    return self.parse_for_in(Stmt::BareExp(init));
+                },
+            }
+            Stmt::BareExp(init)
+        };
+        try!(self.expect(";"));
+
+        let condition = if self.peek_is(";") { Exp::Bool(true) } else { try!(self.parse_expression(false)) };
+        try!(self.expect(";"));
+        let update = if self.peek_is(")") { Stmt::Empty } else { Stmt::BareExp(try!(self.parse_expression(false))) };
+        try!(self.expect(")"));
+
+        let block = try!(self.parse_loop_body());
+        Ok(Stmt::For(Box::new(init), condition, Box::new(update), block))
+    }
+
+    /// Parse the rest of `for (target in exp) stmt`, after the `in`.
+    fn parse_for_in(&mut self, target: Stmt) -> js_error::Result<Stmt> {
+        let exp = try!(self.parse_expression(false));
+        try!(self.expect(")"));
+        let block = try!(self.parse_loop_body());
+        Ok(Stmt::ForIn(Box::new(target), exp, block))
+    }
+
+    /// Parse `switch (exp) { case exp: stmt... default: stmt... }`. Only one `default` clause is
+    /// allowed.
+    fn parse_switch(&mut self) -> js_error::Result<Stmt> {
+        self.pos += 1;
+        let discriminant = try!(self.parse_condition());
+        try!(self.expect("{"));
+
+        let mut cases = Vec::new();
+        let mut has_default = false;
+        while !self.eat("}") {
+            let test = if self.eat("case") {
+                Some(try!(self.parse_expression(false)))
+            } else if self.peek_is("default") {
+                if has_default {
+                    return Err(self.error("More than one default clause in switch statement"));
+                }
+                self.pos += 1;
+                has_default = true;
+                None
+            } else {
+                return Err(self.unexpected());
+            };
+            try!(self.expect(":"));
+
+            let in_switch = self.in_switch;
+            self.in_switch = true;
+            let mut block = Vec::new();
+            while !self.peek_is("case") && !self.peek_is("default") && !self.peek_is("}") {
+                block.push(try!(self.parse_statement()));
+            }
+            self.in_switch = in_switch;
+            cases.push((test, block));
+        }
+        Ok(Stmt::Switch(discriminant, cases))
+    }
+
+    fn parse_try(&mut self) -> js_error::Result<Stmt> {
+        self.pos += 1;
+        let try_block = try!(self.parse_block());
+
+        let (catch_var, catch_block) = if self.eat("catch") {
+            try!(self.expect("("));
+            let name = try!(self.expect_ident());
+            try!(self.expect(")"));
+            (name, try!(self.parse_block()))
+        } else {
+            (String::new(), Vec::new())
+        };
+
+        let finally_block = if self.eat("finally") {
+            try!(self.parse_block())
+        } else if catch_var.is_empty() {
+            return Err(self.unexpected());
+        } else {
+            Vec::new()
+        };
+        Ok(Stmt::Try(try_block, catch_var, catch_block, finally_block))
+    }
+
+    /// Parse a function declaration or expression. Only expressions may leave out the name.
+    fn parse_function(&mut self, is_decl: bool) -> js_error::Result<Exp> {
+        self.pos += 1;
+        let name = match self.peek() {
+            Some(t) if t.kind == TokenKind::Ident => Some(try!(self.expect_ident())),
+            _ if is_decl => return Err(self.unexpected()),
+            _ => None,
+        };
+
+        try!(self.expect("("));
+        let mut params = Vec::new();
+        if !self.eat(")") {
+            loop {
+                params.push(try!(self.expect_ident()));
+                if self.eat(")") {
+                    break;
+                }
+                try!(self.expect(","));
+            }
+        }
+
+        // Jumps can't leave a function, so its body starts with no enclosing labels or loops.
+        let labels = mem::replace(&mut self.labels, Vec::new());
+        let in_loop = mem::replace(&mut self.in_loop, false);
+        let in_switch = mem::replace(&mut self.in_switch, false);
+        let body = self.parse_block();
+        self.labels = labels;
+        self.in_loop = in_loop;
+        self.in_switch = in_switch;
+        Ok(Exp::Defun(name, params, Rc::new(try!(body))))
+    }
+
+    /// Parse a comma-separated sequence of expressions (ES5 §11.14).
+    fn parse_expression(&mut self, no_in: bool) -> js_error::Result<Exp> {
+        let mut exp = try!(self.parse_assignment(no_in));
+        while self.eat(",") {
+            let next = try!(self.parse_assignment(no_in));
+            exp = Exp::Comma(Box::new(exp), Box::new(next));
+        }
+        Ok(exp)
+    }
+
+    /// Parse `lhs = exp` or `lhs op= exp`, which are right-associative, or a conditional
+    /// expression. Whether the target can be assigned to is checked when it's evaluated.
+    fn parse_assignment(&mut self, no_in: bool) -> js_error::Result<Exp> {
+        let lhs = try!(self.parse_conditional(no_in));
+        if self.eat("=") {
+            let rhs = try!(self.parse_assignment(no_in));
+            return Ok(Exp::Assign(Box::new(lhs), Box::new(rhs)));
+        }
+        if let Some(op) = self.compound_assign_op() {
+            self.pos += 1;
+            let rhs = try!(self.parse_assignment(no_in));
+            return Ok(Exp::CompoundAssign(Box::new(lhs), op, Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    /// Parse `condition ? exp : exp`. The branch between `?` and `:` may always use `in`.
+    fn parse_conditional(&mut self, no_in: bool) -> js_error::Result<Exp> {
+        let condition = try!(self.parse_binary(1, no_in));
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let if_exp = try!(self.parse_assignment(false));
+        try!(self.expect(":"));
+        let else_exp = try!(self.parse_assignment(no_in));
+        Ok(Exp::Cond(Box::new(condition), Box::new(if_exp), Box::new(else_exp)))
+    }
+
+    /// The binary operator at the next token, with its precedence. Higher precedences bind
+    /// tighter; `**` is handled by `parse_exponent`. With `no_in`, `in` isn't an operator, so that
+    /// it can end the target of a `for`-`in` loop.
+    fn binary_op(&self, no_in: bool) -> Option<(BinOp, usize)> {
+        let t = match self.peek() {
+            Some(t) if t.kind == TokenKind::Punct || t.kind == TokenKind::Keyword => t,
+            _ => return None,
+        };
+        let op = match &*t.text {
+            "||" => (BinOp::Or, 1),
+            "&&" => (BinOp::And, 2),
+            "|" => (BinOp::BitOr, 3),
+            "^" => (BinOp::BitXor, 4),
+            "&" => (BinOp::BitAnd, 5),
+            "==" => (BinOp::Eql, 6),
+            "!=" => (BinOp::Neq, 6),
+            "===" => (BinOp::EqlStrict, 6),
+            "!==" => (BinOp::NeqStrict, 6),
+            "<" => (BinOp::Lt, 7),
+            ">" => (BinOp::Gt, 7),
+            "<=" => (BinOp::Le, 7),
+            ">=" => (BinOp::Ge, 7),
+            "instanceof" => (BinOp::InstanceOf, 7),
+            "in" if !no_in => (BinOp::In, 7),
+            "<<" => (BinOp::ShiftLeft, 8),
+            ">>" => (BinOp::ShiftRight, 8),
+            ">>>" => (BinOp::ShiftRightUnsigned, 8),
+            "+" => (BinOp::Plus, 9),
+            "-" => (BinOp::Minus, 9),
+            "*" => (BinOp::Star, 10),
+            "/" => (BinOp::Slash, 10),
+            "%" => (BinOp::Mod, 10),
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Parse a chain of left-associative binary operators of at least `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: usize, no_in: bool) -> js_error::Result<Exp> {
+        let mut left = try!(self.parse_exponent());
+        while let Some((op, precedence)) = self.binary_op(no_in) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let right = try!(self.parse_binary(precedence + 1, no_in));
+            left = Exp::BinExp(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// `**` is right-associative, binds tighter than the other binary operators, and can't have a
+    /// unary expression as its base (ES2016 §12.6).
+    fn parse_exponent(&mut self) -> js_error::Result<Exp> {
+        let is_unary = self.peek().map_or(false, |t| {
+            (t.kind == TokenKind::Punct || t.kind == TokenKind::Keyword) &&
+                ["!", "-", "+", "~", "typeof", "delete", "void"].contains(&&*t.text)
+        });
+        let base = try!(self.parse_unary());
+        if !self.peek_is("**") {
+            return Ok(base);
+        }
+        if is_unary {
+            return Err(self.error("Unary operator used immediately before exponentiation expression"));
+        }
+        self.pos += 1;
+        let exponent = try!(self.parse_exponent());
+        Ok(Exp::BinExp(Box::new(base), BinOp::Exponent, Box::new(exponent)))
+    }
+
+    fn parse_unary(&mut self) -> js_error::Result<Exp> {
+        let op = match self.peek() {
+            Some(t) if t.kind == TokenKind::Punct || t.kind == TokenKind::Keyword => t.text.clone(),
+            _ => return self.parse_postfix(),
+        };
+        let make: fn(Box<Exp>) -> Exp = match &*op {
+            "!" => Exp::LogNot,
+            "-" => Exp::Neg,
+            "+" => Exp::Pos,
+            "~" => Exp::BitNot,
+            "typeof" => Exp::TypeOf,
+            "void" => Exp::Void,
+            "delete" => Exp::Delete,
+            "++" => Exp::PreInc,
+            "--" => Exp::PreDec,
+            _ => return self.parse_postfix(),
+        };
+        self.pos += 1;
+        Ok(make(Box::new(try!(self.parse_unary()))))
+    }
+
+    /// Parse `exp++` or `exp--`, which can't have a line terminator before the operator.
+    fn parse_postfix(&mut self) -> js_error::Result<Exp> {
+        let exp = try!(self.parse_lhs());
+        let make: fn(Box<Exp>) -> Exp = match self.peek() {
+            Some(t) if !t.newline_before && t.is("++") => Exp::PostInc,
+            Some(t) if !t.newline_before && t.is("--") => Exp::PostDec,
+            _ => return Ok(exp),
+        };
+        self.pos += 1;
+        Ok(make(Box::new(exp)))
+    }
+
+    /// Parse calls, property accesses and `new` expressions (ES5 §11.2).
+    fn parse_lhs(&mut self) -> js_error::Result<Exp> {
+        let mut exp = if self.peek_is("new") { try!(self.parse_new()) } else { try!(self.parse_primary()) };
+        loop {
+            if self.peek_is("(") {
+                let args = try!(self.parse_arguments());
+                exp = Exp::Call(Box::new(exp), args);
+            } else if let Some(member) = try!(self.parse_member(&exp)) {
+                exp = member;
+            } else {
+                return Ok(exp);
+            }
+        }
+    }
+
+    /// Parse `new callee(args)`, where the arguments are optional. The callee can't contain
+    /// calls, so `new a.B().c` reads `c` from a new `a.B`.
+    fn parse_new(&mut self) -> js_error::Result<Exp> {
+        self.pos += 1;
+        let mut callee = if self.peek_is("new") { try!(self.parse_new()) } else { try!(self.parse_primary()) };
+        while let Some(member) = try!(self.parse_member(&callee)) {
+            callee = member;
+        }
+        let args = if self.peek_is("(") { try!(self.parse_arguments()) } else { Vec::new() };
+        Ok(Exp::NewObject(Box::new(callee), args))
+    }
+
+    /// Parse `.name` or `[key]` after `object`, if either follows.
+    fn parse_member(&mut self, object: &Exp) -> js_error::Result<Option<Exp>> {
+        if self.eat(".") {
+            // Reserved words are allowed as property names.
+            let name = match self.peek() {
+                Some(t) if t.kind == TokenKind::Ident || t.kind == TokenKind::Keyword => t.text.clone(),
+                _ => return Err(self.unexpected()),
+            };
+            self.pos += 1;
+            Ok(Some(Exp::InstanceVar(Box::new(object.clone()), name)))
+        } else if self.eat("[") {
+            let key = try!(self.parse_expression(false));
+            try!(self.expect("]"));
+            Ok(Some(Exp::KeyAccessor(Box::new(object.clone()), Box::new(key))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_arguments(&mut self) -> js_error::Result<Vec<Exp>> {
+        try!(self.expect("("));
+        let mut args = Vec::new();
+        if self.eat(")") {
+            return Ok(args);
+        }
+        loop {
+            args.push(try!(self.parse_assignment(false)));
+            if self.eat(")") {
+                return Ok(args);
+            }
+            try!(self.expect(","));
+        }
+    }
+
+    fn parse_primary(&mut self) -> js_error::Result<Exp> {
+        let token = match self.peek() {
+            Some(t) => t.clone(),
+            None => return Err(self.unexpected()),
+        };
+
+        let exp = match token.kind {
+            TokenKind::Ident if token.text == "undefined" => Exp::Undefined,
+            TokenKind::Ident => Exp::Var(token.text),
+            TokenKind::Num => Exp::Float(string_to_number(&token.text)),
+            TokenKind::Str => Exp::Str(String::from(&token.text[1..token.text.len() - 1])),
+            TokenKind::Regex => return Err(self.error("Regular expression literals are not supported")),
+            TokenKind::Keyword | TokenKind::Punct => match &*token.text {
+                "this" => Exp::Var(String::from("this")),
+                "true" => Exp::Bool(true),
+                "false" => Exp::Bool(false),
+                "null" => Exp::Null,
+                "function" => return self.parse_function(false),
+                "[" => return self.parse_array(),
+                "{" => return self.parse_object(),
+                "(" => {
+                    self.pos += 1;
+                    let exp = try!(self.parse_expression(false));
+                    try!(self.expect(")"));
+                    return Ok(exp);
+                }
+                _ => return Err(self.unexpected()),
+            },
+        };
+        self.pos += 1;
+        Ok(exp)
+    }
+
+    /// Parse an array literal. Elided elements, as in `[1, , 2]`, are `undefined`.
+    fn parse_array(&mut self) -> js_error::Result<Exp> {
+        self.pos += 1;
+        let mut elems = Vec::new();
+        loop {
+            if self.eat("]") {
+                return Ok(Exp::Array(elems));
+            }
+            if self.eat(",") {
+                elems.push(Exp::Undefined);
+                continue;
+            }
+            elems.push(try!(self.parse_assignment(false)));
+            if !self.eat(",") {
+                try!(self.expect("]"));
+                return Ok(Exp::Array(elems));
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> js_error::Result<Exp> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        loop {
+            if self.eat("}") {
+                return Ok(Exp::Object(fields));
+            }
+            let key = try!(self.parse_property_name());
+            try!(self.expect(":"));
+            fields.push((key, Box::new(try!(self.parse_assignment(false)))));
+            if !self.eat(",") {
+                try!(self.expect("}"));
+                return Ok(Exp::Object(fields));
+            }
+        }
+    }
+
+    /// Parse an object literal's key: an identifier or reserved word, a string, or a number.
+    fn parse_property_name(&mut self) -> js_error::Result<String> {
+        let token = match self.peek() {
+            Some(t) => t.clone(),
+            None => return Err(self.unexpected()),
+        };
+        let key = match token.kind {
+            TokenKind::Ident | TokenKind::Keyword => token.text,
+            TokenKind::Str => match unescape(&token.text[1..token.text.len() - 1]) {
+                Some(key) => key,
+                None => return Err(self.error("Invalid string literal")),
+            },
+            TokenKind::Num => number_to_string(string_to_number(&token.text)),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use jsrs_common::js_error::JsError;
+
+    use ast::{BinOp, Exp, Stmt};
+    use ast::Exp::*;
+    use ast::Stmt::*;
+
+    fn var(name: &str) -> Exp {
+        Var(String::from(name))
+    }
+
+    fn call(name: &str) -> Exp {
+        Call(Box::new(var(name)), Vec::new())
+    }
+
+    fn error_message(source: &str) -> String {
+        match parse(source) {
+            Err(JsError::ParseError(message)) => message,
+            result => panic!("expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_asi() {
+        assert_eq!(vec![Decl(String::from("a"), Float(1.0)), Decl(String::from("b"), Float(2.0))],
+                   parse("var a = 1\nvar b = 2").unwrap());
+        let sum = BinExp(Box::new(var("b")), BinOp::Plus, Box::new(var("c")));
+        assert_eq!(vec![BareExp(Exp::Assign(Box::new(var("a")), Box::new(sum)))], parse("a = b\n+ c").unwrap());
+        assert_eq!(vec![BareExp(var("a")), BareExp(PreInc(Box::new(var("b"))))], parse("a\n++b").unwrap());
+        let a0 = KeyAccessor(Box::new(var("a")), Box::new(Float(0.0)));
+        assert_eq!(vec![BareExp(Exp::CompoundAssign(Box::new(a0), BinOp::ShiftRightUnsigned, Box::new(Float(1.0)))),
+                        BareExp(Exp::CompoundAssign(Box::new(var("b")), BinOp::Slash, Box::new(Float(2.0))))],
+                   parse("a[0] >>>= 1\nb /= 2").unwrap());
+        assert_eq!(vec![FnDecl(Defun(Some(String::from("f")), Vec::new(),
+                                     Rc::new(vec![Ret(Undefined), BareExp(var("x"))])))],
+                   parse("function f() { return\nx }").unwrap());
+        assert_eq!(vec![BareExp(Defun(Some(String::from("g")), Vec::new(), Rc::new(Vec::new())))],
+                   parse("(function g() {})").unwrap());
+        assert_eq!(vec![If(var("a"), vec![BareExp(call("b"))], Vec::new())], parse("if (a)\nb()").unwrap());
+        assert_eq!(2, parse("var o = {\na: 1,\nb: 2\n}\nf()").unwrap().len());
+        assert_eq!(vec![For(Box::new(Empty), Bool(true), Box::new(Empty), Vec::new())],
+                   parse("for (\n;\n;\n) {}").unwrap());
+        assert_eq!(2, parse("do {} while (a)\nwhile (b) {}").unwrap().len());
+        assert!(parse("a b").is_err());
+    }
+
+    #[test]
+    fn test_expressions() {
+        let product = BinExp(Box::new(Float(2.0)), BinOp::Star, Box::new(Float(3.0)));
+        assert_eq!(vec![BareExp(BinExp(Box::new(Float(1.0)), BinOp::Plus, Box::new(product)))],
+                   parse("1 + 2 * 3;").unwrap());
+
+        let power = BinExp(Box::new(Float(3.0)), BinOp::Exponent, Box::new(Float(2.0)));
+        assert_eq!(vec![BareExp(BinExp(Box::new(Float(2.0)), BinOp::Exponent, Box::new(power)))],
+                   parse("2 ** 3 ** 2;").unwrap());
+        assert!(parse("-2 ** 2;").is_err());
+
+        let new_b = NewObject(Box::new(InstanceVar(Box::new(var("a")), String::from("B"))), Vec::new());
+        assert_eq!(vec![BareExp(InstanceVar(Box::new(new_b), String::from("c")))], parse("new a.B().c;").unwrap());
+        assert_eq!(vec![BareExp(Array(vec![Float(1.0), Undefined, Float(2.0)]))], parse("[1, , 2,];").unwrap());
+
+        let in_a = BinExp(Box::new(var("x")), BinOp::In, Box::new(var("a")));
+        let cond = Cond(Box::new(var("c")), Box::new(in_a), Box::new(Void(Box::new(Float(0.0)))));
+        assert_eq!(vec![BareExp(Comma(Box::new(cond), Box::new(Delete(Box::new(var("d"))))))],
+                   parse("c ? x in a : void 0, delete d;").unwrap());
+
+        // `in` inside parentheses is an operator even in a `for` header.
+        let in_o = BinExp(Box::new(var("k")), BinOp::In, Box::new(var("o")));
+        assert_eq!(vec![For(Box::new(Stmt::Decl(String::from("x"), in_o)), Bool(true), Box::new(Empty), Vec::new())],
+                   parse("for (var x = (k in o);;) {}").unwrap());
+        assert_eq!(vec![ForIn(Box::new(BareExp(var("k"))), var("o"), Vec::new())], parse("for (k in o) {}").unwrap());
+
+        let b_is_1 = Exp::Assign(Box::new(var("b")), Box::new(Float(1.0)));
+        let a_plus_b = Exp::CompoundAssign(Box::new(var("a")), BinOp::Plus, Box::new(b_is_1));
+        assert_eq!(vec![If(Exp::Assign(Box::new(var("x")), Box::new(a_plus_b)), Vec::new(), Vec::new())],
+                   parse("if (x = a += b = 1) {}").unwrap());
+        let init = Comma(Box::new(Exp::Assign(Box::new(var("i")), Box::new(Float(0.0)))),
+                         Box::new(Exp::Assign(Box::new(var("j")), Box::new(Float(1.0)))));
+        assert_eq!(vec![For(Box::new(BareExp(init)), Bool(true), Box::new(Empty), Vec::new())],
+                   parse("for (i = 0, j = 1;;) {}").unwrap());
+    }
+
+    #[test]
+    fn test_switch() {
+        let cases = vec![(Some(Float(1.0)), vec![BareExp(call("a")), Break(None)]),
+                         (None, Vec::new()),
+                         (Some(Float(2.0)), vec![BareExp(call("b"))])];
+        assert_eq!(vec![Switch(var("x"), cases)],
+                   parse("switch (x) { case 1: a(); break; default: case 2: b() }").unwrap());
+        assert_eq!(vec![Switch(var("x"), Vec::new())], parse("switch (x) {}").unwrap());
+        assert!(error_message("switch (x) { default: default: }").contains("More than one default clause"));
+        assert!(parse("switch (x) { a(); }").is_err());
+    }
+
+    #[test]
+    fn test_jumps() {
+        let loop_body = vec![Continue(Some(String::from("a")))];
+        assert_eq!(vec![Labelled(String::from("a"), Box::new(Labelled(String::from("b"),
+                                                                     Box::new(While(Bool(true), loop_body)))))],
+                   parse("a: b: while (true) { continue a; }").unwrap());
+        assert_eq!(vec![Labelled(String::from("a"), Box::new(Break(Some(String::from("a"))))), BareExp(call("f"))],
+                   parse("a: { break a; }\nf()").unwrap());
+        assert_eq!(vec![While(Bool(true), vec![Break(None), BareExp(var("a"))])],
+                   parse("while (true) { break\na }").unwrap());
+
+        let syntax_error = |source| match parse(source) {
+            Err(JsError::SyntaxError(message)) => message,
+            result => panic!("expected a syntax error, got {:?}", result),
+        };
+        assert!(syntax_error("break;").contains("Illegal break statement"));
+        assert!(syntax_error("switch (x) { case 1: continue; }").contains("Illegal continue statement"));
+        assert!(syntax_error("a: { continue a; }").contains("Undefined label 'a'"));
+        assert!(syntax_error("a: a: ;").contains("Label 'a' has already been declared"));
+        assert!(syntax_error("a: while (true) { (function () { break a; }); }").contains("Undefined label 'a'"));
+        assert!(syntax_error("while (true) { function f() { break; } }").contains("line 1, column 31"));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert!(error_message("var c = 1;\nvar d = ;\n").contains("line 2, column 9"));
+        assert!(error_message("function f() {\n  return 1 +;\n}").contains("line 2, column 13"));
+        assert!(error_message("if (a) {").contains("Unexpected end of input (line 1, column 9)"));
+        assert!(error_message("x = /a/;").contains("line 1, column 5"));
+    }
+}
@@ -0,0 +1,19 @@
+use ast::Stmt;
+use jsrs_common::js_error;
+
+use parser;
+
+/// A whole script, parsed up front so that nothing runs if any part of it fails to parse.
+#[derive(Clone, Debug)]
+pub struct Program {
+    /// The script's top-level statements, in source order.
+    pub body: Vec<Stmt>,
+}
+
+impl Program {
+    /// Parse a script, reporting the first syntax error with the line and column where it was
+    /// found.
+    pub fn parse(source: &str) -> js_error::Result<Program> {
+        Ok(Program { body: try!(parser::parse(source)) })
+    }
+}
@@ -1,8 +1,49 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use jsrs_common::backend::Backend;
+use jsrs_common::types::coerce::AsString;
+use jsrs_common::types::js_obj::JsObjStruct;
 use jsrs_common::types::js_str::JsStrStruct;
-use jsrs_common::types::js_var::{JsKey, JsPtrEnum, JsType, JsVar};
+use jsrs_common::types::js_var::{JsKey, JsPtrEnum, JsPtrTag, JsType, JsVar};
+
+use native::get_proto;
+use number::number_to_string;
 
 pub type JsVarValue = (JsVar, Option<JsPtrEnum>);
-pub type JsReturnValue = Option<JsVarValue>;
+
+/// The completion of evaluating a statement (ES5 §8.9). Abrupt `throw` completions are not
+/// represented here: they travel as the `Err` side of `js_error::Result`, since expressions can
+/// throw as well.
+#[derive(Clone, Debug)]
+pub enum Completion {
+    Normal(JsVarValue),
+    Return(JsVarValue),
+    /// `break`, with an optional label.
+    Break(Option<String>),
+    /// `continue`, with an optional label.
+    Continue(Option<String>),
+}
+
+impl Completion {
+    /// Whether control leaves the enclosing block early.
+    pub fn is_abrupt(&self) -> bool {
+        match *self {
+            Completion::Normal(_) => false,
+            _ => true,
+        }
+    }
+
+    /// The value produced by the statement. `break` and `continue` produce `undefined`.
+    pub fn value(self) -> JsVarValue {
+        match self {
+            Completion::Normal(v) | Completion::Return(v) => v,
+            Completion::Break(_) | Completion::Continue(_) => scalar(JsType::JsUndef),
+        }
+    }
+}
 
 #[inline]
 // Helper to avoid repeating this everywhere
@@ -10,23 +51,78 @@ pub fn scalar(v: JsType) -> (JsVar, Option<JsPtrEnum>) {
     (JsVar::new(v), None)
 }
 
+/// ToString (ES5 §9.8) of a value that isn't on the heap. Numbers are formatted by
+/// `number_to_string` rather than by jsrs_common.
+pub fn scalar_to_string(t: &JsType) -> String {
+    match *t {
+        JsType::JsNum(n) => number_to_string(n),
+        ref t => t.as_string(),
+    }
+}
+
 #[inline]
 pub fn js_str_key(key: &str) -> JsKey {
     JsKey::JsStr(JsStrStruct::new(key))
 }
 
-/// Loads a pointer from the scope, and returns JsError::undefined if not found.
-macro_rules! try_load {
-    ($state:ident, $var:expr, $is_instance_var:expr) => {{
-        let mut state_ref = $state.borrow_mut();
+/// Looks `key` up on `obj`, then on each object in its prototype chain.
+pub fn find_property(obj: &JsObjStruct, key: &JsKey, state: Rc<RefCell<Backend>>) -> Option<JsVar> {
+    if let Some(var) = obj.dict.get(key) {
+        return Some(var.clone());
+    }
+    let mut current = get_proto(obj, state.clone());
+    while let Some(o) = current {
+        if let Some(var) = o.dict.get(key) {
+            return Some(var.clone());
+        }
+        current = get_proto(&o, state.clone());
+    }
+    None
+}
+
+/// Whether a property of `obj` shows up in `for-in`. Properties have no attributes yet, so
+/// natively implemented properties (built-in methods, accessors such as an array's `length`), hidden
+/// `%` keys and the `length` of `String` objects are the non-enumerable ones.
+fn is_enumerable(obj: &JsObjStruct, key: &str, var: &JsVar) -> bool {
+    match var.t {
+        JsType::JsPtr(JsPtrTag::NativeFn { .. }) | JsType::JsPtr(JsPtrTag::NativeVar { .. }) => false,
+        _ => !key.starts_with('%') && !(key == "length" && obj.name == "String"),
+    }
+}
+
+/// The keys `for-in` visits on `obj` (ES5 §12.6.4): its own enumerable properties, then those of
+/// each object in its prototype chain that aren't shadowed. Each object's array indices come first
+/// in ascending order, followed by its other keys in sorted order.
+pub fn enumerable_keys(obj: &JsObjStruct, state: Rc<RefCell<Backend>>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut keys = Vec::new();
+    let mut current = Some(obj.clone());
+
+    while let Some(o) = current {
+        let mut own: Vec<(String, bool)> = o.dict.iter().filter_map(|(key, var)| match *key {
+            JsKey::JsStr(ref s) => Some((s.text.clone(), is_enumerable(&o, &s.text, var))),
+            _ => None,
+        }).collect();
+        // The characters of a `String` object are its index properties (ES5 §15.5.5.2).
+        if o.name == "String" {
+            if let Some(&JsVar { t: JsType::JsNum(len), .. }) = o.dict.get(&js_str_key("length")) {
+                own.extend((0..len as usize).map(|i| (i.to_string(), true)));
+            }
+        }
+        own.sort_by(|&(ref a, _), &(ref b, _)| match (a.parse::<u32>(), b.parse::<u32>()) {
+            (Ok(i), Ok(j)) => i.cmp(&j),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        });
 
-        if $is_instance_var {
-            state_ref.alloc_box.borrow_mut().find_id(&$var.unique).map(|p| p.borrow().clone())
-        } else {            
-            match state_ref.load(&$var.binding) {
-                Ok((_, ptr)) => ptr,
-                Err(_) => return Err(JsError::undefined(&$var.binding.0)),
+        for (key, enumerable) in own {
+            // A non-enumerable property still hides enumerable ones further up the chain.
+            if seen.insert(key.clone()) && enumerable {
+                keys.push(key);
             }
         }
-    }}
+        current = get_proto(&o, state.clone());
+    }
+    keys
 }